@@ -1,19 +1,33 @@
 use serde_json::Value;
 use url::Url;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 use super::RemoteContextLoader;
 
-use compact::CompactionError;
+use compact::{CompactionError, CompactionOptions, InverseContext};
 use context::Context;
-use expand::ExpansionError;
+use creation::ContextCache;
+use expand::{ExpansionError, ExpansionOptions};
+use nodemap::{label_blank_nodes, DefaultNodeGenerator};
 
 use futures::prelude::{await, *};
 
 /// Options that may be passed to either `compact` or `expand`.
+///
+/// JSON objects in the output are `serde_json` maps, which sort their keys,
+/// so `@index`, `@language` and other maps always come out in a stable,
+/// sorted order. With the `preserve_order` feature enabled, `serde_json`
+/// keeps insertion order instead: map keys then follow the input document,
+/// and only `ordered` keeps the keys of these maps sorted.
+#[derive(Default)]
 pub struct JsonLdOptions {
     /// The base IRI of the document. Used to resolve relative references.
+    ///
+    /// This takes precedence over the document URL, when the input is one.
+    /// If neither is given, or the one that applies isn't an absolute IRI,
+    /// relative references are left as they are.
     pub base: Option<String>,
 
     /// When compacting, if single-element arrays should be unpacked.
@@ -22,8 +36,99 @@ pub struct JsonLdOptions {
     /// The context to use when expanding the JSON-LD structures.
     pub expand_context: Option<Value>,
 
-    /// The processing mode, currently unused.
+    /// The processing mode, `json-ld-1.0` if unset. With `json-ld-1.1`, lists
+    /// of lists are allowed. A context with `"@version": 1.1` switches an
+    /// unset processing mode to `json-ld-1.1`, and conflicts with `json-ld-1.0`.
     pub processing_mode: Option<String>,
+
+    /// When expanding, fail with `UndefinedTerm` on keys that aren't a
+    /// keyword, a term, or an IRI, and with `InvalidKeyword` on `@base`,
+    /// `@vocab` and `@container` keys outside a context, instead of silently
+    /// dropping them.
+    pub strict: Option<bool>,
+
+    /// When expanding, sort the `@type` values of each node so the output
+    /// doesn't depend on the order they were written in. When compacting,
+    /// sort the keys of `@language`, `@index` and `@id` maps, even with the
+    /// `preserve_order` feature enabled.
+    pub ordered: Option<bool>,
+
+    /// When expanding, what to do with `@id`, `@type` and `@id`-coerced
+    /// values that are still relative IRIs, for lack of a base IRI. They are
+    /// kept by default.
+    pub relative_iri_handling: Option<RelativeIriHandling>,
+
+    /// When compacting, drop values of a property that are identical to one
+    /// already there. Lists keep all their items.
+    pub deduplicate: Option<bool>,
+
+    /// When expanding, give every node object an `@id`: anonymous nodes get
+    /// a fresh blank node identifier, and existing ones are relabeled.
+    pub label_blank_nodes: Option<bool>,
+
+    /// A cache of processed contexts to go through. Worth sharing between
+    /// calls that keep using the same contexts.
+    pub context_cache: Option<ContextCache>,
+
+    /// When expanding, how deep arrays and objects may nest before failing
+    /// with `MaxDepthExceeded`. Defaults to 256.
+    pub max_depth: Option<usize>,
+
+    /// When compacting, property IRIs to leave as they are, instead of
+    /// compacting them to a term or compact IRI.
+    pub keep_absolute: Option<HashSet<String>>,
+}
+
+/// What expansion does with an IRI it couldn't make absolute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelativeIriHandling {
+    /// Keep the relative IRI as is.
+    Keep,
+
+    /// Leave out the `@id`, `@type` value or node reference it appears in.
+    Drop,
+
+    /// Fail with `ExpansionError::RelativeIRI`.
+    Error,
+}
+
+impl Default for RelativeIriHandling {
+    fn default() -> RelativeIriHandling {
+        RelativeIriHandling::Keep
+    }
+}
+
+impl JsonLdOptions {
+    /// The base IRI to start from: `base` if set, else the document URL.
+    fn base_iri(&self, input: &Value) -> Option<Url> {
+        self.base
+            .as_ref()
+            .map(String::as_str)
+            .or_else(|| input.as_str())
+            .and_then(|f| Url::parse(f).ok())
+    }
+
+    fn compaction_options(&self) -> CompactionOptions {
+        CompactionOptions {
+            compact_arrays: self.compact_arrays.unwrap_or(true),
+            deduplicate: self.deduplicate.unwrap_or(false),
+            context_cache: self.context_cache.clone(),
+            keep_absolute: self.keep_absolute.clone().unwrap_or_default(),
+            ordered: self.ordered.unwrap_or(false),
+        }
+    }
+
+    fn expansion_options(&self) -> ExpansionOptions {
+        ExpansionOptions {
+            strict: self.strict.unwrap_or(false),
+            ordered: self.ordered.unwrap_or(false),
+            relative_iri_handling: self.relative_iri_handling.unwrap_or_default(),
+            remote_contexts: None,
+            context_cache: self.context_cache.clone(),
+            max_depth: self.max_depth,
+            depth: 0,
+        }
+    }
 }
 
 /// Compacts a JSON-LD structure according to the API specification.
@@ -33,33 +138,36 @@ pub fn compact<T: RemoteContextLoader>(
     context: Value,
     options: JsonLdOptions,
 ) -> Result<Value, CompactionError<T>> {
+    let expansion_options = options.expansion_options();
+    let compaction_options = options.compaction_options();
+
     // 3
     let mut ctx = Context::new();
-    ctx.base_iri = options
-        .base
-        .as_ref()
-        .and_then::<&str, _>(|f| Some(f))
-        .or_else(|| input.as_str())
-        .and_then(|f| Url::parse(f).ok());
+    ctx.base_iri = options.base_iri(&input);
+    ctx.processing_mode = options.processing_mode.clone();
+
+    // the context to compact against starts from the same base and mode
+    let compaction_context = ctx.clone();
 
     // 4
     if let Some(val) = options.expand_context {
         let (_, c) = if let Value::Object(mut val) = val {
             if let Some(val) = val.remove("@context") {
-                await!(ctx.process_context::<T>(val, HashMap::new(),))
+                await!(ctx.process_context_cached::<T>(val, options.context_cache.clone()))
             } else {
-                await!(ctx.process_context::<T>(Value::Object(val), HashMap::new()))
+                await!(ctx
+                    .process_context_cached::<T>(Value::Object(val), options.context_cache.clone()))
             }
         } else {
-            await!(ctx.process_context::<T>(val, HashMap::new()))
+            await!(ctx.process_context_cached::<T>(val, options.context_cache.clone()))
         }
         .map_err(|e| CompactionError::ContextError(e))?;
 
         ctx = c;
     }
 
-    let expanded =
-        await!(ctx.expand::<T>(input)).map_err(|e| CompactionError::ExpansionError(e))?;
+    let expanded = await!(ctx.expand_with_options::<T>(input, expansion_options))
+        .map_err(|e| CompactionError::ExpansionError(e))?;
 
     let context = if let Value::Object(mut val) = context {
         if let Some(val) = val.remove("@context") {
@@ -71,11 +179,59 @@ pub fn compact<T: RemoteContextLoader>(
         context
     };
 
-    await!(Context::compact::<T>(
-        context,
-        expanded,
-        options.compact_arrays.unwrap_or(true),
-    ))
+    await!(compaction_context.compact_with_options::<T>(context, expanded, compaction_options))
+}
+
+/// Processes `context` the way `compact` does, starting from the base IRI and
+/// processing mode in `options`, and builds its inverse for
+/// `compact_with_inverse`.
+#[async]
+pub fn inverse_context<T: RemoteContextLoader>(
+    context: Value,
+    options: JsonLdOptions,
+) -> Result<Arc<InverseContext>, CompactionError<T>> {
+    let mut ctx = Context::new();
+    ctx.base_iri = options.base_iri(&Value::Null);
+    ctx.processing_mode = options.processing_mode.clone();
+
+    let context = if let Value::Object(mut val) = context {
+        if let Some(val) = val.remove("@context") {
+            val
+        } else {
+            Value::Object(val)
+        }
+    } else {
+        context
+    };
+
+    let (_, ctx) = await!(ctx.process_context_cached::<T>(context, options.context_cache.clone()))
+        .map_err(|e| CompactionError::ContextError(e))?;
+
+    Ok(Arc::new(InverseContext::new(&ctx)))
+}
+
+/// Compacts a JSON-LD structure like `compact`, against a context that was
+/// already processed and inverted with `inverse_context`.
+///
+/// `context` is the local context as given, which only ends up as the
+/// `@context` entry of the output. Compaction uses the base IRI and
+/// processing mode `inverse` was built with, so build it from the same
+/// `options`. Building `inverse` once and sharing it saves redoing that work
+/// for every document compacted against it.
+#[async]
+pub fn compact_with_inverse<T: RemoteContextLoader>(
+    input: Value,
+    context: Value,
+    inverse: Arc<InverseContext>,
+    options: JsonLdOptions,
+) -> Result<Value, CompactionError<T>> {
+    let expansion_options = options.expansion_options();
+    let compaction_options = options.compaction_options();
+
+    let expanded = await!(_expand::<T>(input, options, expansion_options))
+        .map_err(|e| CompactionError::ExpansionError(e))?;
+
+    Context::compact_with_inverse(&inverse, &context, &expanded, &compaction_options)
 }
 
 /// Expands a JSON-LD structure according to the API specification.
@@ -83,31 +239,1445 @@ pub fn compact<T: RemoteContextLoader>(
 pub fn expand<T: RemoteContextLoader>(
     input: Value,
     options: JsonLdOptions,
+) -> Result<Value, ExpansionError<T>> {
+    let expansion_options = options.expansion_options();
+
+    await!(_expand::<T>(input, options, expansion_options))
+}
+
+/// Expands a JSON-LD structure like `expand`, and also returns the content of
+/// every remote context that was dereferenced along the way, keyed on URL.
+#[async]
+pub fn expand_with_remote_contexts<T: RemoteContextLoader>(
+    input: Value,
+    options: JsonLdOptions,
+) -> Result<(Value, HashMap<String, Value>), ExpansionError<T>> {
+    let remote_contexts = Arc::new(Mutex::new(HashMap::new()));
+    let expansion_options = ExpansionOptions {
+        remote_contexts: Some(remote_contexts.clone()),
+        ..options.expansion_options()
+    };
+
+    let expanded = await!(_expand::<T>(input, options, expansion_options))?;
+    let remote_contexts = remote_contexts.lock().unwrap().clone();
+
+    Ok((expanded, remote_contexts))
+}
+
+#[async]
+fn _expand<T: RemoteContextLoader>(
+    input: Value,
+    options: JsonLdOptions,
+    expansion_options: ExpansionOptions,
 ) -> Result<Value, ExpansionError<T>> {
     // 3
     let mut ctx = Context::new();
-    ctx.base_iri = options
-        .base
-        .as_ref()
-        .and_then::<&str, _>(|f| Some(f))
-        .or_else(|| input.as_str())
-        .and_then(|f| Url::parse(f).ok());
+    ctx.base_iri = options.base_iri(&input);
+    ctx.processing_mode = options.processing_mode.clone();
 
     // 4
     if let Some(val) = options.expand_context {
-        let (_, c) = if let Value::Object(mut val) = val {
+        let (remote_contexts, c) = if let Value::Object(mut val) = val {
             if let Some(val) = val.remove("@context") {
-                await!(ctx.process_context::<T>(val, HashMap::new(),))
+                await!(ctx.process_context_cached::<T>(val, options.context_cache.clone()))
             } else {
-                await!(ctx.process_context::<T>(Value::Object(val), HashMap::new()))
+                await!(ctx
+                    .process_context_cached::<T>(Value::Object(val), options.context_cache.clone()))
             }
         } else {
-            await!(ctx.process_context::<T>(val, HashMap::new()))
+            await!(ctx.process_context_cached::<T>(val, options.context_cache.clone()))
         }
         .map_err(|e| ExpansionError::ContextExpansionError(e))?;
+        expansion_options.record_remote_contexts(remote_contexts);
 
         ctx = c;
     }
 
-    await!(ctx.expand::<T>(input))
+    let label = options.label_blank_nodes.unwrap_or(false);
+    let mut expanded = await!(ctx.expand_with_options::<T>(input, expansion_options))?;
+
+    if label {
+        label_blank_nodes(&mut expanded, &mut DefaultNodeGenerator::new());
+    }
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+    use serde_json::Map;
+    use test_util::{json, StubLoader};
+
+    #[test]
+    fn ordered_index_map_keys_are_sorted() {
+        let context = json(r#"{"p": {"@id": "http://example.com/p", "@container": "@index"}}"#);
+        let input = json(
+            r#"{
+                "@context": {"p": {"@id": "http://example.com/p", "@container": "@index"}},
+                "p": {"zz": "last", "mm": "middle", "aa": "first"}
+            }"#,
+        );
+
+        let options = JsonLdOptions {
+            ordered: Some(true),
+            ..Default::default()
+        };
+
+        let compacted = compact::<StubLoader>(input, context, options)
+            .wait()
+            .unwrap();
+        let text = compacted.to_string();
+
+        let aa = text.find("\"aa\"").unwrap();
+        let mm = text.find("\"mm\"").unwrap();
+        let zz = text.find("\"zz\"").unwrap();
+        assert!(aa < mm && mm < zz, "{}", text);
+    }
+
+    #[test]
+    fn json_literals_compact_to_their_raw_value() {
+        let context =
+            json(r#"{"@version": 1.1, "j": {"@id": "http://example.com/j", "@type": "@json"}}"#);
+        let input = json(
+            r#"{
+                "@context": {"@version": 1.1, "j": {"@id": "http://example.com/j", "@type": "@json"}},
+                "@id": "http://example.com/node",
+                "j": {"a": [1, 2]}
+            }"#,
+        );
+
+        let options = JsonLdOptions {
+            processing_mode: Some("json-ld-1.1".to_owned()),
+            ..Default::default()
+        };
+
+        let compacted = compact::<StubLoader>(input, context, options)
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["j"], json(r#"{"a": [1, 2]}"#));
+    }
+
+    #[test]
+    fn compact_with_inverse_matches_compact() {
+        let context = json(r#"{"j": {"@id": "http://example.com/j", "@type": "@json"}}"#);
+        let input = json(
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/j": {"@value": [1, 2], "@type": "@json"}
+            }"#,
+        );
+
+        let options = || JsonLdOptions {
+            base: Some("http://example.com/".to_owned()),
+            processing_mode: Some("json-ld-1.1".to_owned()),
+            ..Default::default()
+        };
+
+        let inverse = inverse_context::<StubLoader>(context.clone(), options())
+            .wait()
+            .unwrap();
+        let with_inverse =
+            compact_with_inverse::<StubLoader>(input.clone(), context.clone(), inverse, options())
+                .wait()
+                .unwrap();
+        let compacted = compact::<StubLoader>(input, context, options())
+            .wait()
+            .unwrap();
+
+        assert_eq!(with_inverse, compacted);
+        assert_eq!(with_inverse["@id"], json(r#""node""#));
+        assert_eq!(with_inverse["j"], json("[1, 2]"));
+    }
+
+    #[test]
+    fn context_keywords_in_node_objects_only_fail_in_strict_mode() {
+        let input = || {
+            json(
+                r#"{
+                    "@id": "http://example.com/node",
+                    "@vocab": "http://example.com/",
+                    "http://example.com/p": "v"
+                }"#,
+            )
+        };
+
+        let expanded = expand::<StubLoader>(input(), Default::default())
+            .wait()
+            .unwrap();
+        assert!(expanded[0].get("@vocab").is_none());
+        assert_eq!(
+            expanded[0]["http://example.com/p"][0]["@value"],
+            json(r#""v""#)
+        );
+
+        let options = JsonLdOptions {
+            strict: Some(true),
+            ..Default::default()
+        };
+
+        match expand::<StubLoader>(input(), options).wait() {
+            Err(ExpansionError::InvalidKeyword) => (),
+            other => panic!("expected InvalidKeyword, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nest_is_only_a_keyword_in_json_ld_1_1() {
+        let input = || {
+            json(
+                r#"{
+                    "@id": "http://example.com/node",
+                    "@nest": {"http://example.com/p": "v"}
+                }"#,
+            )
+        };
+
+        let expanded = expand::<StubLoader>(input(), Default::default())
+            .wait()
+            .unwrap();
+        assert!(expanded[0].get("http://example.com/p").is_none());
+
+        let options = JsonLdOptions {
+            processing_mode: Some("json-ld-1.1".to_owned()),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(input(), options).wait().unwrap();
+        assert_eq!(
+            expanded[0]["http://example.com/p"][0]["@value"],
+            json(r#""v""#)
+        );
+    }
+
+    #[test]
+    fn relative_context_references_resolve_against_the_referencing_context() {
+        let input = json(
+            r#"{
+                "@context": "contexts/a.jsonld",
+                "@id": "http://example.com/node",
+                "a": "x",
+                "b": "y"
+            }"#,
+        );
+
+        let options = JsonLdOptions {
+            base: Some("http://example.com/doc".to_owned()),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(input, options).wait().unwrap();
+        assert_eq!(
+            expanded[0]["http://example.com/a"][0]["@value"],
+            json(r#""x""#)
+        );
+        assert_eq!(
+            expanded[0]["http://example.com/b"][0]["@value"],
+            json(r#""y""#)
+        );
+    }
+
+    #[test]
+    fn type_values_are_vocab_compacted() {
+        let context = json(r#"{"@vocab": "http://schema.org/"}"#);
+        let input = json(
+            r#"{
+                "@id": "http://example.com/alice",
+                "@type": ["http://schema.org/Person"]
+            }"#,
+        );
+
+        let compacted = compact::<StubLoader>(input, context, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["@type"], json(r#""Person""#));
+    }
+
+    #[test]
+    fn top_level_array_elements_keep_their_own_contexts() {
+        let input = json(
+            r#"[
+                {"@context": {"name": "http://example.com/a"}, "@id": "_:x", "name": "x"},
+                {"@context": {"name": "http://example.com/b"}, "@id": "_:y", "name": "y"}
+            ]"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            expanded[0]["http://example.com/a"][0]["@value"],
+            json(r#""x""#)
+        );
+        assert_eq!(
+            expanded[1]["http://example.com/b"][0]["@value"],
+            json(r#""y""#)
+        );
+        assert!(expanded[1].get("http://example.com/a").is_none());
+    }
+
+    #[test]
+    fn type_coercion_applies_to_each_list_element() {
+        let input = json(
+            r#"{
+                "@context": {
+                    "n": {
+                        "@id": "http://example.com/n",
+                        "@type": "http://www.w3.org/2001/XMLSchema#integer",
+                        "@container": "@list"
+                    }
+                },
+                "@id": "http://example.com/node",
+                "n": [1, 2]
+            }"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        let list = &expanded[0]["http://example.com/n"][0]["@list"];
+        assert_eq!(
+            *list,
+            json(
+                r#"[
+                    {"@value": 1, "@type": "http://www.w3.org/2001/XMLSchema#integer"},
+                    {"@value": 2, "@type": "http://www.w3.org/2001/XMLSchema#integer"}
+                ]"#
+            )
+        );
+    }
+
+    #[test]
+    fn string_expand_context_is_loaded_as_a_remote_context() {
+        let input = json(r#"{"@id": "http://example.com/node", "a": "x", "b": "y"}"#);
+
+        let options = JsonLdOptions {
+            expand_context: Some(json(r#""http://example.com/contexts/a.jsonld""#)),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(input, options).wait().unwrap();
+        assert_eq!(
+            expanded[0]["http://example.com/a"][0]["@value"],
+            json(r#""x""#)
+        );
+        assert_eq!(
+            expanded[0]["http://example.com/b"][0]["@value"],
+            json(r#""y""#)
+        );
+    }
+
+    #[test]
+    fn numeric_index_values_are_rejected() {
+        let input = json(
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/p": {"@value": "v", "@index": 1}
+            }"#,
+        );
+
+        match expand::<StubLoader>(input, Default::default()).wait() {
+            Err(ExpansionError::InvalidIndexValue) => (),
+            other => panic!("expected InvalidIndexValue, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ids_compact_against_the_base_and_properties_against_the_vocab() {
+        let context = json(r#"{"@vocab": "http://example.com/"}"#);
+        let input = json(
+            r#"{
+                "@id": "http://example.com/name",
+                "http://example.com/name": {"@id": "http://example.com/name"}
+            }"#,
+        );
+
+        let options = JsonLdOptions {
+            base: Some("http://example.com/a/b".to_owned()),
+            ..Default::default()
+        };
+
+        let compacted = compact::<StubLoader>(input, context, options)
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["@id"], json(r#""../name""#));
+        assert_eq!(compacted["name"], json(r#"{"@id": "../name"}"#));
+    }
+
+    fn strict() -> JsonLdOptions {
+        JsonLdOptions {
+            strict: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn strict_mode_rejects_keys_that_would_be_dropped() {
+        let inputs = [
+            r#"{"@id": "http://example.com/node", "unknown": "x"}"#,
+            r#"{"@id": "http://example.com/node", "@unknown": "x"}"#,
+        ];
+
+        for input in inputs.iter() {
+            let expanded = expand::<StubLoader>(json(input), Default::default())
+                .wait()
+                .unwrap();
+            assert_eq!(expanded, json("[]"));
+
+            match expand::<StubLoader>(json(input), strict()).wait() {
+                Err(ExpansionError::UndefinedTerm) => (),
+                other => panic!("expected UndefinedTerm, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn strict_mode_accepts_mapped_and_null_mapped_keys() {
+        let input = || {
+            json(
+                r#"{
+                    "@context": {"@vocab": "http://example.com/", "ignored": null},
+                    "@id": "http://example.com/node",
+                    "name": "x",
+                    "ex:other": "y",
+                    "ignored": "z"
+                }"#,
+            )
+        };
+
+        let expanded = expand::<StubLoader>(input(), strict()).wait().unwrap();
+        assert_eq!(
+            expanded,
+            expand::<StubLoader>(input(), Default::default())
+                .wait()
+                .unwrap()
+        );
+        assert_eq!(
+            expanded[0]["http://example.com/name"][0]["@value"],
+            json(r#""x""#)
+        );
+        assert!(expanded[0].get("ignored").is_none());
+    }
+
+    #[test]
+    fn set_objects_inside_lists_are_rejected() {
+        let inputs = [
+            r#"{
+                "@context": {"l": {"@id": "http://example.com/l", "@container": "@list"}},
+                "@id": "http://example.com/node",
+                "l": [{"@set": [1]}, 2]
+            }"#,
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/l": {"@list": [1, {"@set": [2]}]}
+            }"#,
+        ];
+
+        for input in inputs.iter() {
+            match expand::<StubLoader>(json(input), Default::default()).wait() {
+                Err(ExpansionError::InvalidSetOrListObject) => (),
+                other => panic!("expected InvalidSetOrListObject, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn set_objects_as_list_values_unwrap_into_the_list() {
+        let inputs = [
+            r#"{
+                "@context": {"l": {"@id": "http://example.com/l", "@container": "@list"}},
+                "@id": "http://example.com/node",
+                "l": {"@set": [1, 2]}
+            }"#,
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/l": {"@list": {"@set": [1, 2]}}
+            }"#,
+        ];
+
+        for input in inputs.iter() {
+            let expanded = expand::<StubLoader>(json(input), Default::default())
+                .wait()
+                .unwrap();
+            assert_eq!(
+                expanded[0]["http://example.com/l"],
+                json(r#"[{"@list": [{"@value": 1}, {"@value": 2}]}]"#)
+            );
+        }
+    }
+
+    fn json_ld_1_1() -> JsonLdOptions {
+        JsonLdOptions {
+            processing_mode: Some("json-ld-1.1".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    const ID_MAP_DOCUMENT: &'static str = r#"{
+        "@context": {"m": {"@id": "http://example.com/m", "@container": ["@id"]}},
+        "@id": "http://example.com/node",
+        "m": {
+            "http://example.com/x": {"http://example.com/p": "a"},
+            "_:b": {"http://example.com/p": "b"},
+            "@none": {"http://example.com/p": "c"}
+        }
+    }"#;
+
+    #[test]
+    fn id_maps_expand_their_keys_to_ids() {
+        let expanded = expand::<StubLoader>(json(ID_MAP_DOCUMENT), json_ld_1_1())
+            .wait()
+            .unwrap();
+        let nodes = expanded[0]["http://example.com/m"].as_array().unwrap();
+
+        let node = |value: &str| {
+            nodes
+                .iter()
+                .find(|node| node["http://example.com/p"][0]["@value"] == json(value))
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(node(r#""a""#)["@id"], json(r#""http://example.com/x""#));
+        assert_eq!(node(r#""b""#)["@id"], json(r#""_:b""#));
+        assert!(node(r#""c""#).get("@id").is_none());
+        assert_eq!(nodes.len(), 3);
+    }
+
+    #[test]
+    fn id_maps_round_trip_through_compaction() {
+        let input = json(ID_MAP_DOCUMENT);
+        let context = json(r#"{"m": {"@id": "http://example.com/m", "@container": ["@id"]}}"#);
+
+        let compacted = compact::<StubLoader>(input.clone(), context, json_ld_1_1())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["m"], input["m"]);
+    }
+
+    /// `objects` node objects nested through `http://example.com/p`, around
+    /// a string: every object and the string count as one level.
+    fn nested_document(objects: usize) -> Value {
+        let mut value = json(r#""leaf""#);
+        for _ in 0..objects {
+            let mut node = Map::new();
+            node.insert("http://example.com/p".to_owned(), value);
+            value = Value::Object(node);
+        }
+
+        value
+    }
+
+    #[test]
+    fn documents_at_the_depth_limit_expand() {
+        let options = JsonLdOptions {
+            max_depth: Some(4),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(nested_document(3), options)
+            .wait()
+            .unwrap();
+        let leaf = &expanded[0]["http://example.com/p"][0]["http://example.com/p"][0]
+            ["http://example.com/p"][0];
+        assert_eq!(*leaf, json(r#"{"@value": "leaf"}"#));
+    }
+
+    #[test]
+    fn documents_past_the_depth_limit_fail() {
+        let options = JsonLdOptions {
+            max_depth: Some(4),
+            ..Default::default()
+        };
+
+        match expand::<StubLoader>(nested_document(4), options).wait() {
+            Err(ExpansionError::MaxDepthExceeded) => (),
+            other => panic!("expected MaxDepthExceeded, got {:?}", other),
+        }
+    }
+
+    /// Compacts `input` against its own context, which should give it back.
+    fn assert_round_trips(input: &str, options: JsonLdOptions) {
+        let input = json(input);
+        let context = input["@context"].clone();
+
+        let compacted = compact::<StubLoader>(input.clone(), context, options)
+            .wait()
+            .unwrap();
+        assert_eq!(compacted, input);
+    }
+
+    #[test]
+    fn reverse_set_terms_round_trip() {
+        assert_round_trips(
+            r#"{
+                "@context": {
+                    "children": {"@reverse": "http://example.com/parent", "@container": "@set"}
+                },
+                "@id": "http://example.com/p",
+                "children": [{"@id": "http://example.com/c"}]
+            }"#,
+            Default::default(),
+        );
+    }
+
+    #[test]
+    fn reverse_index_maps_round_trip() {
+        assert_round_trips(
+            r#"{
+                "@context": {
+                    "children": {"@reverse": "http://example.com/parent", "@container": "@index"}
+                },
+                "@id": "http://example.com/p",
+                "children": {
+                    "first": {"@id": "http://example.com/c1"},
+                    "second": {"@id": "http://example.com/c2"}
+                }
+            }"#,
+            Default::default(),
+        );
+    }
+
+    #[test]
+    fn reverse_id_maps_round_trip() {
+        assert_round_trips(
+            r#"{
+                "@context": {
+                    "children": {"@reverse": "http://example.com/parent", "@container": "@id"}
+                },
+                "@id": "http://example.com/p",
+                "children": {
+                    "http://example.com/c1": {"http://example.com/name": "one"},
+                    "http://example.com/c2": {"http://example.com/name": "two"}
+                }
+            }"#,
+            json_ld_1_1(),
+        );
+    }
+
+    #[test]
+    fn keep_absolute_leaves_only_the_given_predicates_alone() {
+        let context = json(r#"{"@vocab": "http://example.com/", "ex": "http://example.com/"}"#);
+        let input = json(
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/name": "x",
+                "http://example.com/source": "y"
+            }"#,
+        );
+
+        let mut keep_absolute = HashSet::new();
+        keep_absolute.insert("http://example.com/source".to_owned());
+        let options = JsonLdOptions {
+            keep_absolute: Some(keep_absolute),
+            ..Default::default()
+        };
+
+        let compacted = compact::<StubLoader>(input, context, options)
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["name"], json(r#""x""#));
+        assert_eq!(compacted["http://example.com/source"], json(r#""y""#));
+        assert!(compacted.get("source").is_none());
+    }
+
+    #[test]
+    fn deduplicate_collapses_repeated_values_but_not_lists() {
+        let context = json(
+            r#"{
+                "name": "http://example.com/name",
+                "l": {"@id": "http://example.com/l", "@container": "@list"}
+            }"#,
+        );
+        let input = || {
+            json(
+                r#"{
+                    "@context": {
+                        "name": "http://example.com/name",
+                        "l": {"@id": "http://example.com/l", "@container": "@list"}
+                    },
+                    "@id": "http://example.com/node",
+                    "name": "x",
+                    "http://example.com/name": ["x", {"@value": "x"}],
+                    "l": [1, 1]
+                }"#,
+            )
+        };
+
+        let options = JsonLdOptions {
+            deduplicate: Some(true),
+            ..Default::default()
+        };
+
+        let compacted = compact::<StubLoader>(input(), context.clone(), options)
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["name"], json(r#""x""#));
+        assert_eq!(compacted["l"], json("[1, 1]"));
+
+        let compacted = compact::<StubLoader>(input(), context, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["name"], json(r#"["x", "x", "x"]"#));
+    }
+
+    const RELATIVE_IRI_DOCUMENT: &'static str = r#"{
+        "@id": "relative",
+        "@type": ["Relative"],
+        "http://example.com/p": {"@id": "other"},
+        "http://example.com/q": "v"
+    }"#;
+
+    fn relative_iri_handling(handling: RelativeIriHandling) -> JsonLdOptions {
+        JsonLdOptions {
+            relative_iri_handling: Some(handling),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn relative_iris_are_kept_by_default() {
+        let expanded = expand::<StubLoader>(
+            json(RELATIVE_IRI_DOCUMENT),
+            relative_iri_handling(RelativeIriHandling::Keep),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            expanded,
+            json(
+                r#"[{
+                    "@id": "relative",
+                    "@type": ["Relative"],
+                    "http://example.com/p": [{"@id": "other"}],
+                    "http://example.com/q": [{"@value": "v"}]
+                }]"#
+            )
+        );
+        assert_eq!(
+            expanded,
+            expand::<StubLoader>(json(RELATIVE_IRI_DOCUMENT), Default::default())
+                .wait()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn relative_iris_can_be_dropped() {
+        let expanded = expand::<StubLoader>(
+            json(RELATIVE_IRI_DOCUMENT),
+            relative_iri_handling(RelativeIriHandling::Drop),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            expanded,
+            json(r#"[{"http://example.com/q": [{"@value": "v"}]}]"#)
+        );
+    }
+
+    #[test]
+    fn relative_iris_can_be_rejected() {
+        match expand::<StubLoader>(
+            json(RELATIVE_IRI_DOCUMENT),
+            relative_iri_handling(RelativeIriHandling::Error),
+        )
+        .wait()
+        {
+            Err(ExpansionError::RelativeIRI) => (),
+            other => panic!("expected RelativeIRI, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_remote_context_limit_is_per_expansion() {
+        // each document loads two remote contexts, so together they go far
+        // past the limit while each one stays under it
+        for _ in 0..10 {
+            let input = json(
+                r#"{
+                    "@context": "http://example.com/contexts/a.jsonld",
+                    "@id": "http://example.com/node",
+                    "a": "x"
+                }"#,
+            );
+
+            let expanded = expand::<StubLoader>(input, Default::default())
+                .wait()
+                .unwrap();
+            assert_eq!(
+                expanded[0]["http://example.com/a"][0]["@value"],
+                json(r#""x""#)
+            );
+        }
+    }
+
+    #[test]
+    fn lists_of_lists_are_rejected_in_json_ld_1_0() {
+        let inputs = [
+            r#"{"http://example.com/l": {"@list": [{"@list": ["a"]}]}}"#,
+            r#"{
+                "@context": {"l": {"@id": "http://example.com/l", "@container": "@list"}},
+                "l": [["a"]]
+            }"#,
+        ];
+
+        for input in inputs.iter() {
+            match expand::<StubLoader>(json(input), Default::default()).wait() {
+                Err(ExpansionError::ListOfLists) => (),
+                other => panic!("expected ListOfLists, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn lists_of_lists_expand_to_nested_list_objects_in_json_ld_1_1() {
+        let inputs = [
+            r#"{"http://example.com/l": {"@list": [{"@list": ["a"]}]}}"#,
+            r#"{
+                "@context": {"l": {"@id": "http://example.com/l", "@container": "@list"}},
+                "l": [["a"]]
+            }"#,
+        ];
+
+        for input in inputs.iter() {
+            let expanded = expand::<StubLoader>(json(input), json_ld_1_1())
+                .wait()
+                .unwrap();
+
+            assert_eq!(
+                expanded,
+                json(r#"[{"http://example.com/l": [{"@list": [{"@list": [{"@value": "a"}]}]}]}]"#)
+            );
+        }
+    }
+
+    #[test]
+    fn doubly_nested_lists_round_trip() {
+        assert_round_trips(
+            r#"{
+                "@context": {"l": {"@id": "http://example.com/l", "@container": "@list"}},
+                "@id": "http://example.com/node",
+                "l": [[["a", "b"], ["c"]], [["d"]]]
+            }"#,
+            json_ld_1_1(),
+        );
+    }
+
+    #[test]
+    fn compacting_a_list_of_lists_fails_in_json_ld_1_0() {
+        // the input's own context allows the nested list on expansion, the
+        // context compacted against is plain 1.0
+        let input = json(
+            r#"{
+                "@context": {
+                    "@version": 1.1,
+                    "l": {"@id": "http://example.com/l", "@container": "@list"}
+                },
+                "l": [["a"]]
+            }"#,
+        );
+        let context = json(r#"{"l": {"@id": "http://example.com/l", "@container": "@list"}}"#);
+
+        match compact::<StubLoader>(input, context, Default::default()).wait() {
+            Err(CompactionError::CompactionToListOfLists) => (),
+            other => panic!("expected CompactionToListOfLists, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_string_language_tagged_values_are_rejected() {
+        let input = json(r#"{"http://example.com/p": {"@value": 5, "@language": "en"}}"#);
+
+        match expand::<StubLoader>(input, Default::default()).wait() {
+            Err(ExpansionError::InvalidLanguageTaggedString) => (),
+            other => panic!("expected InvalidLanguageTaggedString, got {:?}", other),
+        }
+    }
+
+    fn expanded_id(base: &str, id: &str) -> Value {
+        let mut input = Map::new();
+        input.insert("@id".to_owned(), Value::String(id.to_owned()));
+        input.insert("http://example.com/p".to_owned(), json(r#""v""#));
+
+        let options = JsonLdOptions {
+            base: Some(base.to_owned()),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(Value::Object(input), options)
+            .wait()
+            .unwrap();
+        expanded[0]["@id"].clone()
+    }
+
+    #[test]
+    fn references_resolve_against_the_base_per_rfc_3986() {
+        let base = "http://example.com/dir/doc?x=1";
+        let cases = [
+            ("#frag", "http://example.com/dir/doc?x=1#frag"),
+            ("?q", "http://example.com/dir/doc?q"),
+            ("/a", "http://example.com/a"),
+            ("path2", "http://example.com/dir/path2"),
+            ("../up", "http://example.com/up"),
+        ];
+
+        for &(id, resolved) in cases.iter() {
+            assert_eq!(expanded_id(base, id), Value::String(resolved.to_owned()));
+        }
+    }
+
+    #[test]
+    fn references_a_urn_base_cannot_resolve_are_left_alone() {
+        assert_eq!(
+            expanded_id("urn:example:doc", "relative"),
+            json(r#""relative""#)
+        );
+    }
+
+    #[test]
+    fn none_keys_round_trip_in_language_and_index_maps() {
+        assert_round_trips(
+            r#"{
+                "@context": {
+                    "label": {"@id": "http://example.com/label", "@container": "@language"}
+                },
+                "@id": "http://example.com/node",
+                "label": {"en": "Hello", "@none": "plain"}
+            }"#,
+            json_ld_1_1(),
+        );
+        assert_round_trips(
+            r#"{
+                "@context": {"m": {"@id": "http://example.com/m", "@container": "@index"}},
+                "@id": "http://example.com/node",
+                "m": {"k": "indexed", "@none": "unindexed"}
+            }"#,
+            json_ld_1_1(),
+        );
+    }
+
+    #[test]
+    fn empty_index_map_keys_expand_without_an_index() {
+        let input = json(
+            r#"{
+                "@context": {"m": {"@id": "http://example.com/m", "@container": "@index"}},
+                "m": {"": "x", "k": "y"}
+            }"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            expanded,
+            json(
+                r#"[{"http://example.com/m": [{"@value": "x"}, {"@value": "y", "@index": "k"}]}]"#
+            )
+        );
+    }
+
+    const MIXED_LANGUAGE_VALUES: &'static str = r#"{
+        "http://example.com/label": [
+            {"@value": "Hello", "@language": "en"},
+            {"@value": "Bonjour", "@language": "fr"},
+            {"@value": "plain"}
+        ]
+    }"#;
+
+    #[test]
+    fn language_maps_file_plain_values_under_none() {
+        let context =
+            json(r#"{"label": {"@id": "http://example.com/label", "@container": "@language"}}"#);
+
+        let compacted = compact::<StubLoader>(json(MIXED_LANGUAGE_VALUES), context, json_ld_1_1())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            compacted["label"],
+            json(r#"{"en": "Hello", "fr": "Bonjour", "@none": "plain"}"#)
+        );
+    }
+
+    #[test]
+    fn plain_values_prefer_a_plain_term_over_a_language_map() {
+        let context = json(
+            r#"{
+                "label": {"@id": "http://example.com/label", "@container": "@language"},
+                "plain": "http://example.com/label"
+            }"#,
+        );
+
+        let compacted = compact::<StubLoader>(json(MIXED_LANGUAGE_VALUES), context, json_ld_1_1())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            compacted["label"],
+            json(r#"{"en": "Hello", "fr": "Bonjour"}"#)
+        );
+        assert_eq!(compacted["plain"], json(r#""plain""#));
+    }
+
+    #[test]
+    fn string_values_in_id_maps_keep_their_own_id() {
+        let input = json(
+            r#"{
+                "@context": {"m": {"@id": "http://example.com/m", "@container": "@id"}},
+                "m": {"http://example.com/key": "http://example.com/value"}
+            }"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, json_ld_1_1()).wait().unwrap();
+        assert_eq!(
+            expanded,
+            json(r#"[{"http://example.com/m": [{"@id": "http://example.com/value"}]}]"#)
+        );
+    }
+
+    const UNSORTED_TYPES: &'static str = r#"{
+        "@id": "http://example.com/node",
+        "@type": ["http://example.com/B", "http://example.com/C", "http://example.com/A"]
+    }"#;
+
+    #[test]
+    fn types_keep_document_order_by_default() {
+        let expanded = expand::<StubLoader>(json(UNSORTED_TYPES), Default::default())
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            expanded[0]["@type"],
+            json(r#"["http://example.com/B", "http://example.com/C", "http://example.com/A"]"#)
+        );
+    }
+
+    #[test]
+    fn types_are_sorted_under_ordered() {
+        let options = JsonLdOptions {
+            ordered: Some(true),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(json(UNSORTED_TYPES), options)
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            expanded[0]["@type"],
+            json(r#"["http://example.com/A", "http://example.com/B", "http://example.com/C"]"#)
+        );
+    }
+
+    #[test]
+    fn context_is_the_first_key_of_compacted_output() {
+        let context = json(r#"{"a": "http://example.com/a", "z": "http://example.com/z"}"#);
+        let input = json(
+            r#"{
+                "http://example.com/z": "last",
+                "@id": "http://example.com/node",
+                "http://example.com/a": "first"
+            }"#,
+        );
+
+        let compacted = compact::<StubLoader>(input, context, Default::default())
+            .wait()
+            .unwrap();
+
+        let keys = compacted.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(keys[0], "@context");
+        assert_eq!(keys.len(), 4);
+    }
+
+    const DOCUMENT_URL: &'static str = r#""http://example.com/document""#;
+
+    fn with_base(base: &str) -> JsonLdOptions {
+        JsonLdOptions {
+            base: Some(base.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_base_option_wins_over_the_document_url() {
+        assert_eq!(
+            with_base("http://example.com/base").base_iri(&json(DOCUMENT_URL)),
+            Some(Url::parse("http://example.com/base").unwrap())
+        );
+    }
+
+    #[test]
+    fn the_document_url_is_the_base_without_the_option() {
+        assert_eq!(
+            JsonLdOptions::default().base_iri(&json(DOCUMENT_URL)),
+            Some(Url::parse("http://example.com/document").unwrap())
+        );
+    }
+
+    #[test]
+    fn there_is_no_base_without_the_option_or_a_document_url() {
+        assert_eq!(JsonLdOptions::default().base_iri(&json("{}")), None);
+    }
+
+    #[test]
+    fn an_invalid_base_option_does_not_fall_back_to_the_document_url() {
+        assert_eq!(with_base("relative").base_iri(&json(DOCUMENT_URL)), None);
+    }
+
+    #[test]
+    fn empty_graph_wrappers_are_dropped() {
+        let expand_default = |input: &str| {
+            expand::<StubLoader>(json(input), Default::default())
+                .wait()
+                .unwrap()
+        };
+
+        assert_eq!(expand_default(r#"{"@graph": []}"#), json("[]"));
+        assert_eq!(
+            expand_default(
+                r#"{"@graph": [
+                    {"@graph": []},
+                    {"@id": "http://example.com/n", "http://example.com/p": "v"}
+                ]}"#
+            ),
+            json(r#"[{"@id": "http://example.com/n", "http://example.com/p": [{"@value": "v"}]}]"#)
+        );
+    }
+
+    #[test]
+    fn empty_named_graphs_are_kept() {
+        let expanded = expand::<StubLoader>(
+            json(r#"{"@id": "http://example.com/g", "@graph": []}"#),
+            Default::default(),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            expanded,
+            json(r#"[{"@id": "http://example.com/g", "@graph": []}]"#)
+        );
+    }
+
+    #[test]
+    fn expand_with_remote_contexts_returns_every_loaded_context() {
+        let input = json(
+            r#"{
+                "@context": "http://example.com/contexts/a.jsonld",
+                "@id": "http://example.com/node",
+                "a": "x"
+            }"#,
+        );
+
+        let (_, remote_contexts) =
+            expand_with_remote_contexts::<StubLoader>(input, Default::default())
+                .wait()
+                .unwrap();
+
+        assert_eq!(remote_contexts.len(), 2);
+        assert_eq!(
+            remote_contexts["http://example.com/contexts/a.jsonld"],
+            json(r#"["b.jsonld", {"a": "http://example.com/a"}]"#)
+        );
+        assert_eq!(
+            remote_contexts["http://example.com/contexts/b.jsonld"],
+            json(r#"{"b": "http://example.com/b"}"#)
+        );
+    }
+
+    #[test]
+    fn expand_with_remote_contexts_returns_imported_contexts() {
+        let input = json(
+            r#"{
+                "@context": {"@version": 1.1, "@import": "http://example.com/import/outer.jsonld"},
+                "@id": "http://example.com/node",
+                "i": "x"
+            }"#,
+        );
+
+        let (_, remote_contexts) =
+            expand_with_remote_contexts::<StubLoader>(input, Default::default())
+                .wait()
+                .unwrap();
+
+        let mut urls = remote_contexts.keys().collect::<Vec<_>>();
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec![
+                "http://example.com/import/inner.jsonld",
+                "http://example.com/import/outer.jsonld"
+            ]
+        );
+        assert_eq!(
+            remote_contexts["http://example.com/import/inner.jsonld"],
+            json(r#"{"i": "http://example.com/i"}"#)
+        );
+    }
+
+    #[test]
+    fn non_string_ids_are_rejected() {
+        let inputs = [
+            r#"{"@id": ["http://example.com/a", "http://example.com/b"], "http://example.com/p": "v"}"#,
+            r#"{"@id": 5, "http://example.com/p": "v"}"#,
+        ];
+
+        for input in inputs.iter() {
+            match expand::<StubLoader>(json(input), Default::default()).wait() {
+                Err(ExpansionError::InvalidIdValue) => (),
+                other => panic!("expected InvalidIdValue, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn terms_are_selected_by_the_container_the_value_fits() {
+        let context = json(
+            r#"{
+                "items": {"@id": "http://example.com/p", "@container": "@list"},
+                "plain": "http://example.com/p"
+            }"#,
+        );
+        let input = json(
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/p": [{"@list": ["a", "b"]}, "c"]
+            }"#,
+        );
+
+        let compacted = compact::<StubLoader>(input, context, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["items"], json(r#"["a", "b"]"#));
+        assert_eq!(compacted["plain"], json(r#""c""#));
+    }
+
+    #[test]
+    fn reverse_values_with_their_own_reverse_expand_at_their_level() {
+        let input = json(
+            r#"{
+                "@id": "http://example.com/a",
+                "@reverse": {
+                    "http://example.com/knows": {
+                        "@id": "http://example.com/b",
+                        "@reverse": {"http://example.com/knows": {"@id": "http://example.com/c"}}
+                    }
+                }
+            }"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            expanded,
+            json(
+                r#"[{
+                    "@id": "http://example.com/a",
+                    "@reverse": {
+                        "http://example.com/knows": [{
+                            "@id": "http://example.com/b",
+                            "@reverse": {"http://example.com/knows": [{"@id": "http://example.com/c"}]}
+                        }]
+                    }
+                }]"#
+            )
+        );
+    }
+
+    #[test]
+    fn reverse_terms_inside_reverse_become_forward_properties() {
+        let input = json(
+            r#"{
+                "@context": {"knownBy": {"@reverse": "http://example.com/knows"}},
+                "@id": "http://example.com/a",
+                "@reverse": {"knownBy": {"@id": "http://example.com/b"}}
+            }"#,
+        );
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            expanded,
+            json(
+                r#"[{"@id": "http://example.com/a", "http://example.com/knows": [{"@id": "http://example.com/b"}]}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn vocab_relative_iris_win_over_compact_iris() {
+        let context = json(r#"{"@vocab": "http://example.com/", "ex": "http://example.com/"}"#);
+        let input = json(r#"{"@id": "http://example.com/node", "http://example.com/name": "v"}"#);
+
+        let compacted = compact::<StubLoader>(input, context, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["name"], json(r#""v""#));
+        assert!(compacted.get("ex:name").is_none());
+    }
+
+    #[test]
+    fn vocab_coercion_resolves_against_the_vocab_and_id_coercion_against_the_base() {
+        let input = json(
+            r#"{
+                "@context": {
+                    "@vocab": "http://example.com/vocab/",
+                    "v": {"@id": "http://example.com/v", "@type": "@vocab"},
+                    "i": {"@id": "http://example.com/i", "@type": "@id"}
+                },
+                "v": "name",
+                "i": "name"
+            }"#,
+        );
+
+        let options = JsonLdOptions {
+            base: Some("http://example.com/doc/".to_owned()),
+            ..Default::default()
+        };
+
+        let expanded = expand::<StubLoader>(input, options).wait().unwrap();
+        assert_eq!(
+            expanded[0]["http://example.com/v"][0]["@id"],
+            json(r#""http://example.com/vocab/name""#)
+        );
+        assert_eq!(
+            expanded[0]["http://example.com/i"][0]["@id"],
+            json(r#""http://example.com/doc/name""#)
+        );
+    }
+
+    #[test]
+    fn string_reverse_values_are_rejected() {
+        let input = json(r#"{"@id": "http://example.com/a", "@reverse": "x"}"#);
+
+        match expand::<StubLoader>(input, Default::default()).wait() {
+            Err(ExpansionError::InvalidReverseValue) => (),
+            other => panic!("expected InvalidReverseValue, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_properties_round_trip() {
+        assert_round_trips(
+            r#"{
+                "@context": {
+                    "details": "@nest",
+                    "name": {"@id": "http://example.com/name", "@nest": "details"}
+                },
+                "@id": "http://example.com/node",
+                "details": {"name": "x"}
+            }"#,
+            json_ld_1_1(),
+        );
+    }
+
+    #[test]
+    fn graph_aliases_expand_like_the_keyword() {
+        let aliased = json(
+            r#"{
+                "@context": {"data": "@graph"},
+                "data": [{"@id": "http://example.com/n", "http://example.com/p": "v"}]
+            }"#,
+        );
+        let keyword =
+            json(r#"{"@graph": [{"@id": "http://example.com/n", "http://example.com/p": "v"}]}"#);
+
+        assert_eq!(
+            expand::<StubLoader>(aliased, Default::default())
+                .wait()
+                .unwrap(),
+            expand::<StubLoader>(keyword, Default::default())
+                .wait()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn graph_and_an_alias_of_it_collide() {
+        let input = json(
+            r#"{
+                "@context": {"data": "@graph"},
+                "@graph": [{"@id": "http://example.com/a"}],
+                "data": [{"@id": "http://example.com/b"}]
+            }"#,
+        );
+
+        match expand::<StubLoader>(input, Default::default()).wait() {
+            Err(ExpansionError::CollidingKeywords) => (),
+            other => panic!("expected CollidingKeywords, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn documents_that_drop_out_entirely_expand_to_an_empty_array() {
+        let input = json(r#"{"unmapped": "x", "other": {"nested": 1}}"#);
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(expanded, json("[]"));
+    }
+
+    #[test]
+    fn compaction_keeps_types_in_expanded_order() {
+        let context = json(r#"{"@vocab": "http://example.com/"}"#);
+
+        let compacted = compact::<StubLoader>(json(UNSORTED_TYPES), context, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["@type"], json(r#"["B", "C", "A"]"#));
+    }
+
+    #[test]
+    fn value_objects_with_extra_keys_are_rejected() {
+        let inputs = [
+            r#"{"http://example.com/p": {"@value": "v", "@id": "http://example.com/x"}}"#,
+            r#"{"http://example.com/p": {"@value": "v", "@graph": []}}"#,
+            r#"{"http://example.com/p": {"@value": "v", "http://example.com/q": "w"}}"#,
+        ];
+
+        for input in inputs.iter() {
+            match expand::<StubLoader>(json(input), Default::default()).wait() {
+                Err(ExpansionError::InvalidValueObject) => (),
+                other => panic!("expected InvalidValueObject, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn undefined_keys_expand_through_the_vocab() {
+        let input =
+            json(r#"{"@context": {"@vocab": "http://example.com/vocab/"}, "undefined": "v"}"#);
+
+        let expanded = expand::<StubLoader>(input, Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            expanded,
+            json(r#"[{"http://example.com/vocab/undefined": [{"@value": "v"}]}]"#)
+        );
+    }
+
+    #[test]
+    fn compacted_output_keeps_remote_context_references() {
+        let context =
+            json(r#"["http://example.com/contexts/b.jsonld", {"a": "http://example.com/a"}]"#);
+        let input = json(
+            r#"{
+                "@id": "http://example.com/node",
+                "http://example.com/a": "x",
+                "http://example.com/b": "y"
+            }"#,
+        );
+
+        let compacted = compact::<StubLoader>(input, context.clone(), Default::default())
+            .wait()
+            .unwrap();
+        assert_eq!(compacted["@context"], context);
+        assert_eq!(compacted["a"], json(r#""x""#));
+        assert_eq!(compacted["b"], json(r#""y""#));
+    }
 }
@@ -93,6 +93,7 @@ fn run_single_seq(seq: FakeSequence, base_iri: &str) {
             compact_arrays: seq.option.and_then(|f| f.compact_arrays),
             expand_context: None,
             processing_mode: None,
+            ..Default::default()
         },
     )
     .wait();
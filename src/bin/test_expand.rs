@@ -102,6 +102,7 @@ fn run_single_seq(seq: FakeSequence, iri: &str) {
             compact_arrays: None,
             expand_context: ctx,
             processing_mode: None,
+            ..Default::default()
         },
     )
     .wait();
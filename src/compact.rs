@@ -1,14 +1,18 @@
 use super::RemoteContextLoader;
 use context::{Context, Term};
 
-use creation::ContextCreationError;
+use creation::{ContextCache, ContextCreationError};
 use expand::ExpansionError;
 use serde_json::{Map, Value};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::io;
+use std::mem;
+use url::{Position, Url};
 
+use futures::future;
 use futures::prelude::{await, *};
 
 #[derive(Debug)]
@@ -38,6 +42,9 @@ pub enum CompactionError<T: RemoteContextLoader> {
 
     /// Expanding the object to compact failed.
     ExpansionError(ExpansionError<T>),
+
+    /// A term's `@nest` value is neither `@nest` nor a term aliasing it.
+    InvalidNestValue,
 }
 
 impl<T: RemoteContextLoader> fmt::Display for CompactionError<T> {
@@ -60,6 +67,7 @@ impl<T: RemoteContextLoader> Error for CompactionError<T> {
             CompactionError::ContextError(_) => "error parsing the context",
             CompactionError::CompactionToListOfLists => "compaction to list of lists",
             CompactionError::ExpansionError(_) => "error expanding the input",
+            CompactionError::InvalidNestValue => "invalid @nest value",
         }
     }
 
@@ -78,8 +86,14 @@ struct TypeLanguageMap {
     pub language_map: HashMap<String, String>,
 }
 
+/// The inverse of a processed context, which compaction uses to pick terms.
+///
+/// Building it goes over every term definition, so callers that compact many
+/// documents against the same context can build it once and pass it to
+/// `compact_with_inverse`.
 #[derive(Debug)]
-struct InverseContext {
+pub struct InverseContext {
+    context: Context,
     container_map: HashMap<String, HashMap<String, TypeLanguageMap>>,
 }
 
@@ -94,16 +108,170 @@ fn _sort_term(x: &(&String, &Term), y: &(&String, &Term)) -> Ordering {
     }
 }
 
+/// Whether `property` is a term with a `@json` type mapping.
+fn _is_json_term(active_context: &Context, property: Option<&str>) -> bool {
+    property
+        .and_then(|f| active_context.terms.get(f))
+        .and_then(|f| f.type_mapping.as_ref())
+        .map(String::as_str)
+        == Some("@json")
+}
+
+/// Takes the map the compacted values of a property are added to out of
+/// `result`: the object under its `@nest` term, or `result` itself.
+fn _take_nest_result(
+    result: &mut Map<String, Value>,
+    nest_term: &Option<String>,
+) -> Map<String, Value> {
+    match *nest_term {
+        Some(ref nest_term) => match result.remove(nest_term) {
+            Some(Value::Object(obj)) => obj,
+            _ => Map::new(),
+        },
+        None => mem::replace(result, Map::new()),
+    }
+}
+
+/// Puts a map taken with `_take_nest_result` back into `result`.
+fn _put_nest_result(
+    result: &mut Map<String, Value>,
+    nest_term: Option<String>,
+    nest_result: Map<String, Value>,
+) {
+    match nest_term {
+        Some(nest_term) => {
+            result.insert(nest_term, Value::Object(nest_result));
+        }
+        None => *result = nest_result,
+    }
+}
+
+/// Turns `iri` into a reference relative to `base`, if they share a scheme
+/// and authority. Path segments in common are dropped and each remaining
+/// base directory becomes a `../`.
+fn _remove_base(base: &Url, iri: &str) -> String {
+    let parsed = match Url::parse(iri) {
+        Ok(parsed) => parsed,
+        Err(_) => return iri.to_owned(),
+    };
+
+    if base.cannot_be_a_base()
+        || parsed.cannot_be_a_base()
+        || base[..Position::BeforePath] != parsed[..Position::BeforePath]
+    {
+        return iri.to_owned();
+    }
+
+    // a query or fragment alone keeps the path of the base, and a fragment
+    // alone its query as well
+    if parsed.path() == base.path() {
+        if let Some(fragment) = parsed.fragment() {
+            if parsed.query() == base.query() {
+                return format!("#{}", fragment);
+            }
+        }
+
+        if let Some(query) = parsed.query() {
+            return match parsed.fragment() {
+                Some(fragment) => format!("?{}#{}", query, fragment),
+                None => format!("?{}", query),
+            };
+        }
+    }
+
+    let mut base_segments: Vec<_> = base.path().split('/').collect();
+    let mut iri_segments: Vec<_> = parsed.path().split('/').collect();
+
+    // only directories are shared; the last segment of the IRI is kept even
+    // when it's the same as the base's
+    while base_segments.len() > 1 && iri_segments.len() > 1 && base_segments[0] == iri_segments[0] {
+        base_segments.remove(0);
+        iri_segments.remove(0);
+    }
+
+    let mut relative = String::new();
+    if !base_segments.is_empty() {
+        // the last base segment is a file name (or empty), not a directory
+        base_segments.pop();
+        for _ in base_segments {
+            relative.push_str("../");
+        }
+    }
+
+    relative.push_str(&iri_segments.join("/"));
+
+    // an empty path would refer to the base itself, and a colon in the first
+    // segment would be read as ending a scheme
+    if relative.is_empty()
+        || relative
+            .split('/')
+            .next()
+            .map_or(false, |segment| segment.contains(':'))
+    {
+        relative.insert_str(0, "./");
+    }
+
+    if let Some(query) = parsed.query() {
+        relative.push('?');
+        relative.push_str(query);
+    }
+    if let Some(fragment) = parsed.fragment() {
+        relative.push('#');
+        relative.push_str(fragment);
+    }
+
+    relative
+}
+
+/// Flags that change how compaction behaves, passed down through every step.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CompactionOptions {
+    /// Unpack arrays that hold a single value.
+    pub compact_arrays: bool,
+
+    /// Drop values of a property that are identical to one it already has,
+    /// except inside lists.
+    pub deduplicate: bool,
+
+    /// If set, the context to compact against is looked up in and added to
+    /// this cache.
+    pub context_cache: Option<ContextCache>,
+
+    /// Property IRIs that stay absolute instead of being compacted to a term
+    /// or compact IRI.
+    pub keep_absolute: HashSet<String>,
+
+    /// Sort the keys of language, index and id maps, which `serde_json` only
+    /// does on its own without the `preserve_order` feature.
+    pub ordered: bool,
+}
+
 #[derive(PartialEq, Eq)]
 enum TypeOrLanguage {
     Type,
     Language,
 }
 
+/// The loader `reverse_lookup` compacts with, which never gets called since
+/// looking up a term doesn't load remote contexts.
+#[derive(Debug)]
+struct NoLoader;
+
+impl RemoteContextLoader for NoLoader {
+    type Error = io::Error;
+    type Future = future::FutureResult<Value, io::Error>;
+
+    fn load_context(url: String) -> Self::Future {
+        future::err(io::Error::new(io::ErrorKind::NotFound, url))
+    }
+}
+
 impl InverseContext {
+    /// Builds the inverse of `ctx`, which is kept along with it.
     pub fn new(ctx: &Context) -> InverseContext {
         // 1
         let mut result = InverseContext {
+            context: ctx.clone(),
             container_map: HashMap::new(),
         };
 
@@ -185,6 +353,23 @@ impl InverseContext {
         result
     }
 
+    /// Looks up the term or compact IRI that compaction would use for `iri`.
+    ///
+    /// With `vocab` set, `iri` is treated as a property or type IRI, otherwise
+    /// as a document-relative `@id`. Returns `None` if no shorter form exists.
+    pub fn reverse_lookup(&self, iri: &str, vocab: bool) -> Option<String> {
+        self.context
+            ._compact_iri::<NoLoader>(self, iri, None, vocab, false)
+            .ok()
+            .and_then(|compacted| {
+                if compacted == iri {
+                    None
+                } else {
+                    Some(compacted)
+                }
+            })
+    }
+
     fn _select_term(
         &self,
         iri: &str,
@@ -215,22 +400,55 @@ impl InverseContext {
 }
 
 impl Context {
+    /// Compacts the expanded `element` against `context`, which is processed
+    /// on top of this context. Only the base IRI and processing mode of this
+    /// context are expected to be set.
     #[async]
     pub fn compact<T: RemoteContextLoader>(
+        self,
         context: Value,
         element: Value,
         compact_arrays: bool,
     ) -> Result<Value, CompactionError<T>> {
-        let (_, ctx) = await!(Context::new().process_context::<T>(context.clone(), HashMap::new()))
-            .map_err(|e| CompactionError::ContextError(e))?;
+        let options = CompactionOptions {
+            compact_arrays: compact_arrays,
+            ..Default::default()
+        };
+
+        await!(self.compact_with_options::<T>(context, element, options))
+    }
+
+    #[async]
+    pub(crate) fn compact_with_options<T: RemoteContextLoader>(
+        self,
+        context: Value,
+        element: Value,
+        options: CompactionOptions,
+    ) -> Result<Value, CompactionError<T>> {
+        let (_, ctx) = await!(
+            self.process_context_cached::<T>(context.clone(), options.context_cache.clone())
+        )
+        .map_err(|e| CompactionError::ContextError(e))?;
 
         let inverse = InverseContext::new(&ctx);
-        let mut res = Context::_compact(&ctx, &inverse, None, &element, compact_arrays)?;
+        Context::compact_with_inverse(&inverse, &context, &element, &options)
+    }
+
+    /// Compacts the expanded `element` against the context `inverse` was built
+    /// from. `context` is only used as the `@context` entry of the result.
+    pub(crate) fn compact_with_inverse<T: RemoteContextLoader>(
+        inverse: &InverseContext,
+        context: &Value,
+        element: &Value,
+        options: &CompactionOptions,
+    ) -> Result<Value, CompactionError<T>> {
+        let ctx = &inverse.context;
+        let mut res = Context::_compact(ctx, inverse, None, element, options)?;
         if res.is_array() {
             let mut map = Map::new();
             map.insert(
                 ctx._compact_iri(
-                    &inverse, "@graph", None, /* XXX Some(res) */
+                    inverse, "@graph", None, /* XXX Some(res) */
                     true, // XXX is this right???
                     false,
                 )?,
@@ -243,20 +461,34 @@ impl Context {
             && !context.is_null()
             && (!context.is_object() || context.as_object().unwrap().len() > 0)
         {
-            res.as_object_mut()
-                .unwrap()
-                .insert("@context".to_owned(), context.clone());
+            // @context goes first, which matters when the `preserve_order`
+            // feature keeps keys in insertion order
+            let obj = mem::replace(res.as_object_mut().unwrap(), Map::new());
+            let mut map = Map::new();
+            map.insert("@context".to_owned(), context.clone());
+            map.extend(obj);
+
+            res = Value::Object(map);
         }
 
         Ok(res)
     }
 
+    /// Looks up the term or compact IRI that compaction would use for `iri`,
+    /// like `InverseContext::reverse_lookup`.
+    ///
+    /// This builds the inverse of this context on every call, so callers doing
+    /// many lookups should build an `InverseContext` once and use that.
+    pub fn reverse_lookup(&self, iri: &str, vocab: bool) -> Option<String> {
+        InverseContext::new(self).reverse_lookup(iri, vocab)
+    }
+
     fn _compact<T: RemoteContextLoader>(
         active_context: &Context,
         inverse_context: &InverseContext,
         active_property: Option<&str>,
         element: &Value,
-        compact_arrays: bool,
+        options: &CompactionOptions,
     ) -> Result<Value, CompactionError<T>> {
         match *element {
             Value::Array(ref arr) => {
@@ -269,14 +501,14 @@ impl Context {
                         inverse_context,
                         active_property,
                         item,
-                        compact_arrays, // XXX right?
+                        options, // XXX right?
                     )?;
                     if compacted != Value::Null {
                         result.push(compacted);
                     }
                 }
 
-                if result.len() == 1 && compact_arrays {
+                if result.len() == 1 && options.compact_arrays {
                     if let Some(prop) = active_property {
                         if active_context.terms.contains_key(prop) {
                             if active_context.terms[prop].container_mapping == None {
@@ -298,17 +530,53 @@ impl Context {
                 if obj.contains_key("@value") || obj.contains_key("@id") {
                     let res =
                         active_context._compact_value(inverse_context, active_property, obj)?;
-                    if !res.is_array() && !res.is_object() {
+
+                    // a JSON literal compacts to its raw value, which may be an
+                    // object or array itself
+                    let json_literal = _is_json_term(active_context, active_property)
+                        && obj.get("@type").and_then(Value::as_str) == Some("@json")
+                        && obj.get("@value") == Some(&res);
+
+                    if json_literal || (!res.is_array() && !res.is_object()) {
                         return Ok(res);
                     }
                 }
 
+                // a list object inside a @list container, i.e. a list of
+                // lists, compacts to a nested array
+                if let Some(list) = obj.get("@list") {
+                    let container = active_property
+                        .and_then(|f| active_context.terms.get(f))
+                        .and_then(|f| f.container_mapping.as_ref());
+
+                    if container.map(String::as_str) == Some("@list") {
+                        if !active_context.is_json_ld_1_1() {
+                            return Err(CompactionError::CompactionToListOfLists);
+                        }
+
+                        return Context::_compact(
+                            active_context,
+                            inverse_context,
+                            active_property,
+                            list,
+                            options,
+                        );
+                    }
+                }
+
                 // 5
                 let inside_reverse = active_property == Some("@reverse");
 
                 // 6
                 let mut result = Map::new();
 
+                // properties whose value is, so far, a single JSON literal array,
+                // keyed on their nest term and name
+                let mut json_arrays = HashSet::new();
+
+                // language, index and id maps, keyed the same way
+                let mut container_maps = HashSet::new();
+
                 // 7, should be implicitly ordered??
                 for (expanded_property, expanded_value) in obj {
                     if expanded_property == "@id" || expanded_property == "@type" {
@@ -367,19 +635,22 @@ impl Context {
                             inverse_context,
                             Some("@reverse"),
                             &expanded_value,
-                            compact_arrays,
+                            options,
                         )?;
                         let mut new_map = Map::new();
                         if let Value::Object(obj) = compacted_value {
                             for (property, mut value) in obj {
                                 if let Some(term) = active_context.terms.get(&property) {
                                     if term.reverse {
-                                        if (term
-                                            .container_mapping
-                                            .as_ref()
-                                            .and_then(|e| Some(e == "@set"))
-                                            == Some(true)
-                                            || !compact_arrays)
+                                        let container =
+                                            term.container_mapping.as_ref().map(String::as_str);
+
+                                        // @index and @id containers already give a map
+                                        let is_map =
+                                            container == Some("@index") || container == Some("@id");
+
+                                        if (container == Some("@set") || !options.compact_arrays)
+                                            && !is_map
                                             && !value.is_array()
                                         {
                                             value = Value::Array(vec![value].into());
@@ -458,32 +729,40 @@ impl Context {
 
                     // 7.5
                     if expanded_value.as_array().and_then(|f| Some(f.len())) == Some(0) {
-                        let item_active_property = active_context._compact_iri(
+                        let item_active_property = active_context._compact_property(
                             inverse_context,
                             expanded_property,
                             /*Some(expanded_value) XXX*/ None,
-                            true,
                             inside_reverse,
+                            options,
                         )?;
-                        if !result.contains_key(&item_active_property) {
-                            result.insert(item_active_property, Value::Array(Vec::new()));
+                        let nest_term = active_context._nest_term(&item_active_property)?;
+                        let mut nest_result = _take_nest_result(&mut result, &nest_term);
+
+                        if !nest_result.contains_key(&item_active_property) {
+                            nest_result.insert(item_active_property, Value::Array(Vec::new()));
                         } else {
                             let val = Value::Array(
-                                vec![result.remove(&item_active_property).unwrap()].into(),
+                                vec![nest_result.remove(&item_active_property).unwrap()].into(),
                             );
-                            result.insert(item_active_property, val);
+                            nest_result.insert(item_active_property, val);
                         }
+
+                        _put_nest_result(&mut result, nest_term, nest_result);
                     }
 
                     // 7.6
                     for expanded_item in expanded_value.as_array().unwrap() {
-                        let item_active_property = active_context._compact_iri(
+                        let item_active_property = active_context._compact_property(
                             inverse_context,
                             expanded_property,
                             expanded_item.as_object(),
-                            true,
                             inside_reverse,
+                            options,
                         )?;
+                        let nest_term = active_context._nest_term(&item_active_property)?;
+                        let mut nest_result = _take_nest_result(&mut result, &nest_term);
+
                         let mut container: Option<&str> = None;
                         if let Some(container_mapping) = active_context
                             .terms
@@ -505,7 +784,7 @@ impl Context {
                             inverse_context,
                             Some(&item_active_property),
                             to_pass,
-                            compact_arrays,
+                            options,
                         )?;
                         if data.contains_key("@list") {
                             if !compacted_item.is_array() {
@@ -541,20 +820,26 @@ impl Context {
                                 compacted_item = Value::Object(m);
                             } else {
                                 // 7.6.4.3
-                                if result.contains_key(&item_active_property) {
+                                if nest_result.contains_key(&item_active_property) {
                                     return Err(CompactionError::CompactionToListOfLists);
                                 }
                             }
                         }
 
-                        if container == Some("@language") || container == Some("@index") {
+                        if container == Some("@language")
+                            || container == Some("@index")
+                            || container == Some("@id")
+                        {
                             // 7.6.5
-                            if !result.contains_key(&item_active_property) {
+                            if !nest_result.contains_key(&item_active_property) {
                                 let map = Map::new();
-                                result.insert(item_active_property.clone(), Value::Object(map));
+                                nest_result
+                                    .insert(item_active_property.clone(), Value::Object(map));
+                                container_maps
+                                    .insert((nest_term.clone(), item_active_property.clone()));
                             }
 
-                            let map_object = result
+                            let map_object = nest_result
                                 .get_mut(&item_active_property)
                                 .and_then(|f| f.as_object_mut())
                                 .unwrap();
@@ -571,53 +856,141 @@ impl Context {
                                     .unwrap();
                             }
 
-                            let map_key = data[container.unwrap()]
-                                .as_str()
-                                .ok_or(CompactionError::LanguageOrIndexNotString)?;
-                            if !map_object.contains_key(map_key) {
-                                map_object.insert(map_key.to_owned(), compacted_item);
+                            let map_key = if container == Some("@id") {
+                                // id maps carry the @id as the key instead, @none if there is none
+                                let id_key = active_context._compact_iri(
+                                    inverse_context,
+                                    "@id",
+                                    None,
+                                    true,
+                                    false,
+                                )?;
+                                if let Some(obj) = compacted_item.as_object_mut() {
+                                    obj.remove(&id_key);
+                                }
+
+                                match data.get("@id") {
+                                    Some(&Value::String(ref id)) => active_context._compact_iri(
+                                        inverse_context,
+                                        id,
+                                        None,
+                                        false,
+                                        false,
+                                    )?,
+                                    _ => active_context._compact_iri(
+                                        inverse_context,
+                                        "@none",
+                                        None,
+                                        true,
+                                        false,
+                                    )?,
+                                }
+                            } else if let Some(key) = data.get(container.unwrap()) {
+                                key.as_str()
+                                    .ok_or(CompactionError::LanguageOrIndexNotString)?
+                                    .to_owned()
+                            } else {
+                                // items without a language or index go under @none
+                                active_context._compact_iri(
+                                    inverse_context,
+                                    "@none",
+                                    None,
+                                    true,
+                                    false,
+                                )?
+                            };
+                            if !map_object.contains_key(&map_key) {
+                                map_object.insert(map_key, compacted_item);
                             } else {
-                                let mut val = map_object.remove(map_key).unwrap();
+                                let mut val = map_object.remove(&map_key).unwrap();
                                 if val.is_array() {
                                     val.as_array_mut().unwrap().push(compacted_item);
                                 } else {
                                     val = Value::Array(vec![val, compacted_item].into());
                                 }
 
-                                map_object.insert(map_key.to_owned(), val);
+                                map_object.insert(map_key, val);
                             }
                         } else {
+                            // a JSON literal array is a single value, not a list of them
+                            let json_literal =
+                                _is_json_term(active_context, Some(&item_active_property))
+                                    && data.get("@type").and_then(Value::as_str) == Some("@json");
+                            let mut values = compacted_item.is_array() && !json_literal;
+
                             // 7.6.6.1
-                            if (!compact_arrays
+                            if (!options.compact_arrays
                                 || container == Some("@set")
                                 || container == Some("@list")
                                 || expanded_property == "@list"
                                 || expanded_property == "@graph")
-                                && !compacted_item.is_array()
+                                && !values
                             {
                                 compacted_item = Value::Array(vec![compacted_item].into());
+                                values = true;
                             }
 
-                            if !result.contains_key(&item_active_property) {
-                                result.insert(item_active_property, compacted_item);
+                            let json_key = (nest_term.clone(), item_active_property.clone());
+                            if !nest_result.contains_key(&item_active_property) {
+                                if compacted_item.is_array() && !values {
+                                    json_arrays.insert(json_key);
+                                }
+
+                                nest_result.insert(item_active_property, compacted_item);
                             } else {
-                                let mut val = result.remove(&item_active_property).unwrap();
-                                let mut varr = if let Value::Array(ar) = compacted_item {
-                                    ar
-                                } else {
-                                    vec![compacted_item].into()
+                                let mut val = nest_result.remove(&item_active_property).unwrap();
+                                if json_arrays.remove(&json_key) {
+                                    val = Value::Array(vec![val].into());
+                                }
+
+                                let mut varr = match compacted_item {
+                                    Value::Array(ar) => {
+                                        if values {
+                                            ar
+                                        } else {
+                                            vec![Value::Array(ar)].into()
+                                        }
+                                    }
+                                    other => vec![other].into(),
                                 };
 
+                                if options.deduplicate
+                                    && container != Some("@list")
+                                    && !data.contains_key("@list")
+                                {
+                                    varr.retain(|item| match val {
+                                        Value::Array(ref arr) => !arr.contains(item),
+                                        ref other => other != item,
+                                    });
+                                }
+
                                 if val.is_array() {
                                     val.as_array_mut().unwrap().append(&mut varr);
-                                } else {
+                                } else if !varr.is_empty() {
                                     varr.insert(0, val);
                                     val = Value::Array(varr);
                                 }
 
-                                result.insert(item_active_property, val);
+                                nest_result.insert(item_active_property, val);
+                            }
+                        }
+
+                        _put_nest_result(&mut result, nest_term, nest_result);
+                    }
+                }
+
+                if options.ordered {
+                    for (nest_term, property) in container_maps {
+                        let mut nest_result = _take_nest_result(&mut result, &nest_term);
+                        if let Some(map) = nest_result.get_mut(&property) {
+                            if let Value::Object(ref mut map) = *map {
+                                let mut entries: Vec<_> =
+                                    mem::replace(map, Map::new()).into_iter().collect();
+                                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                                map.extend(entries);
                             }
                         }
+                        _put_nest_result(&mut result, nest_term, nest_result);
                     }
                 }
 
@@ -628,6 +1001,40 @@ impl Context {
         }
     }
 
+    /// Returns the term the values of `property` are nested under, if its
+    /// definition has a `@nest` value.
+    fn _nest_term<T: RemoteContextLoader>(
+        &self,
+        property: &str,
+    ) -> Result<Option<String>, CompactionError<T>> {
+        match self.terms.get(property).and_then(|f| f.nest_value.as_ref()) {
+            Some(nest_term) => {
+                if nest_term != "@nest" && self.expand_iri(nest_term, false, true) != "@nest" {
+                    return Err(CompactionError::InvalidNestValue);
+                }
+
+                Ok(Some(nest_term.to_owned()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compacts the IRI of a property, unless it is one to keep absolute.
+    fn _compact_property<T: RemoteContextLoader>(
+        &self,
+        inverse_context: &InverseContext,
+        iri: &str,
+        value: Option<&Map<String, Value>>,
+        reverse: bool,
+        options: &CompactionOptions,
+    ) -> Result<String, CompactionError<T>> {
+        if options.keep_absolute.contains(iri) {
+            return Ok(iri.to_owned());
+        }
+
+        self._compact_iri(inverse_context, iri, value, true, reverse)
+    }
+
     fn _compact_iri<T: RemoteContextLoader>(
         &self,
         inverse_context: &InverseContext,
@@ -652,6 +1059,12 @@ impl Context {
             if reverse {
                 type_language = TypeOrLanguage::Type;
                 type_language_value = "@reverse";
+
+                // reverse values are node objects, so they may go in an id map
+                if self.is_json_ld_1_1() {
+                    containers.push("@id");
+                }
+
                 containers.push("@set");
             } else if let Some(ref item) = value {
                 // 2.6
@@ -773,6 +1186,10 @@ impl Context {
                         // 2.7.2
                         type_language = TypeOrLanguage::Type;
                         type_language_value = "@id";
+
+                        if self.is_json_ld_1_1() {
+                            containers.push("@id");
+                        }
                     }
 
                     // 2.7.3
@@ -790,6 +1207,19 @@ impl Context {
             // 2.8
             containers.push("@none");
 
+            // 1.1 lets values without an @index go under @none in index maps
+            if self.is_json_ld_1_1() && value.map_or(true, |item| !item.contains_key("@index")) {
+                containers.push("@index");
+            }
+
+            // 1.1 lets values with only an @value go under @none in language
+            // maps, after any plain term
+            if self.is_json_ld_1_1()
+                && value.map_or(false, |item| item.len() == 1 && item.contains_key("@value"))
+            {
+                containers.push("@language");
+            }
+
             // 2.10
             let mut preferred_values = Vec::new();
 
@@ -847,7 +1277,7 @@ impl Context {
         }
 
         let mut compact_iri: Option<String> = None;
-        for (term, def) in &self.terms {
+        for (term, def) in self.terms.iter() {
             // 5.1
             if term.contains(":") {
                 continue;
@@ -878,8 +1308,11 @@ impl Context {
             return Ok(compact_iri.unwrap());
         }
 
-        if !vocab && self.base_iri != None {
-            //            println!("todo transform IRI this is bad");
+        // 7
+        if !vocab {
+            if let Some(ref base_iri) = self.base_iri {
+                return Ok(_remove_base(base_iri, iri));
+            }
         }
 
         return Ok(iri.to_owned());
@@ -1000,3 +1433,129 @@ impl Context {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+    use test_util::{json, StubLoader};
+
+    #[test]
+    fn reverse_lookup_finds_terms_and_compact_iris() {
+        let context = json(r#"{"ex": "http://example.com/", "name": "http://example.com/name"}"#);
+        let (_, ctx) = Context::new()
+            .process_context::<StubLoader>(context, HashMap::new())
+            .wait()
+            .unwrap();
+        let inverse = InverseContext::new(&ctx);
+
+        assert_eq!(
+            inverse.reverse_lookup("http://example.com/name", true),
+            Some("name".to_owned())
+        );
+        assert_eq!(
+            inverse.reverse_lookup("http://example.com/age", true),
+            Some("ex:age".to_owned())
+        );
+        assert_eq!(inverse.reverse_lookup("http://other.example/", true), None);
+        assert_eq!(
+            ctx.reverse_lookup("http://example.com/name", true),
+            Some("name".to_owned())
+        );
+    }
+
+    #[test]
+    fn remove_base_makes_iris_relative() {
+        let base = Url::parse("http://example.com/a/b/c").unwrap();
+
+        assert_eq!(_remove_base(&base, "http://example.com/a/b/d"), "d");
+        assert_eq!(_remove_base(&base, "http://example.com/a/e"), "../e");
+        assert_eq!(_remove_base(&base, "http://example.com/a/b/"), "./");
+        assert_eq!(_remove_base(&base, "http://example.com/a/b/c"), "c");
+        assert_eq!(
+            _remove_base(&base, "http://example.com/a/b/c#frag"),
+            "#frag"
+        );
+        assert_eq!(_remove_base(&base, "http://example.com/a/b/c?q=1"), "?q=1");
+        assert_eq!(_remove_base(&base, "http://example.com/a/b#f"), "../b#f");
+        assert_eq!(
+            _remove_base(&base, "http://other.example/a/b/d"),
+            "http://other.example/a/b/d"
+        );
+        assert_eq!(
+            _remove_base(&base, "https://example.com/a/b/d"),
+            "https://example.com/a/b/d"
+        );
+        assert_eq!(_remove_base(&base, "_:b0"), "_:b0");
+
+        let base = Url::parse("http://example.com/doc").unwrap();
+        assert_eq!(
+            _remove_base(&base, "http://example.com/?page=2"),
+            "./?page=2"
+        );
+
+        let base = Url::parse("http://example.com/doc?q=1").unwrap();
+        assert_eq!(_remove_base(&base, "http://example.com/doc#f"), "doc#f");
+        assert_eq!(_remove_base(&base, "http://example.com/doc?q=1#f"), "#f");
+    }
+
+    #[test]
+    fn remove_base_round_trips_through_join() {
+        let bases = [
+            "http://example.com/",
+            "http://example.com/doc",
+            "http://example.com/doc?q=1",
+            "http://example.com/dir/",
+            "http://example.com/dir/?q=1",
+            "http://example.com/a/b/c",
+            "http://example.com/a/b/c?q=1#f",
+        ];
+        let iris = [
+            "http://example.com/",
+            "http://example.com/?page=2",
+            "http://example.com/#f",
+            "http://example.com/doc",
+            "http://example.com/doc#f",
+            "http://example.com/doc?q=1",
+            "http://example.com/doc?q=1#f",
+            "http://example.com/doc?q=2",
+            "http://example.com/dir/",
+            "http://example.com/dir/#f",
+            "http://example.com/dir/x",
+            "http://example.com/a:b",
+            "http://example.com/a/b",
+            "http://example.com/a/b#f",
+            "http://example.com/a/b/",
+            "http://example.com/a/b/c",
+            "http://example.com/a/b/c#f",
+            "http://example.com/a/b/c?q=1",
+            "http://example.com/a/b/c/d",
+            "http://example.com/a/e?x#y",
+        ];
+
+        for base in bases.iter() {
+            let base = Url::parse(base).unwrap();
+
+            for iri in iris.iter() {
+                let relative = _remove_base(&base, iri);
+                assert_eq!(
+                    base.join(&relative).unwrap().as_str(),
+                    *iri,
+                    "{} against {} gave {}",
+                    iri,
+                    base,
+                    relative
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn remove_base_keeps_colons_out_of_the_first_segment() {
+        let base = Url::parse("http://example.com/").unwrap();
+
+        assert_eq!(_remove_base(&base, "http://example.com/a:b"), "./a:b");
+        assert_eq!(_remove_base(&base, "http://example.com/a/b:c"), "a/b:c");
+        assert_eq!(_remove_base(&base, "http://example.com/a?b:c"), "a?b:c");
+    }
+}
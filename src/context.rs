@@ -1,19 +1,27 @@
 use std::collections::BTreeMap;
+use std::sync::Arc;
 use url::Url;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct Term {
     pub type_mapping: Option<String>,
     pub iri_mapping: String,
     pub reverse: bool,
     pub container_mapping: Option<String>,
     pub language_mapping: Option<String>,
+    pub nest_value: Option<String>,
+    pub protected: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Context {
     pub base_iri: Option<Url>,
+    /// The base IRI from before an `@base` entry first changed it, which a
+    /// `null` context goes back to. Unset while no `@base` did.
+    pub(crate) original_base_iri: Option<Option<Url>>,
+    pub(crate) processing_mode: Option<String>,
     pub(crate) vocabulary_mapping: Option<String>,
     pub(crate) language: Option<String>,
-    pub(crate) terms: BTreeMap<String, Term>,
+    /// Shared between clones, and only copied when a clone defines a term.
+    pub(crate) terms: Arc<BTreeMap<String, Term>>,
 }
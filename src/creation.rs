@@ -5,9 +5,10 @@ use serde_json::Map as JsonMap;
 use serde_json::Value;
 
 use std::clone::Clone;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 
 use url::Url;
 
@@ -29,6 +30,9 @@ pub enum TermCreationError {
     InvalidContainerMapping,
     InvalidLanguageMapping,
     InvalidTypeMapping,
+    InvalidNestValue,
+    InvalidProtectedValue,
+    ProtectedTermRedefinition,
 }
 
 impl fmt::Display for TermCreationError {
@@ -49,6 +53,9 @@ impl Error for TermCreationError {
             TermCreationError::InvalidContainerMapping => "invalid container mapping",
             TermCreationError::InvalidLanguageMapping => "invalid language mapping",
             TermCreationError::InvalidTypeMapping => "invalid type mapping",
+            TermCreationError::InvalidNestValue => "invalid @nest value",
+            TermCreationError::InvalidProtectedValue => "invalid @protected value",
+            TermCreationError::ProtectedTermRedefinition => "protected term redefinition",
         }
     }
 
@@ -68,6 +75,13 @@ pub enum ContextCreationError<T: RemoteContextLoader> {
     InvalidVocabMapping,
     InvalidLanguageMapping,
     InvalidLocalContext,
+    InvalidImportValue,
+    InvalidProtectedValue,
+    InvalidContextNullification,
+    InvalidVersionValue,
+    ProcessingModeConflict,
+    InvalidPropagateValue,
+    InvalidBaseDirection,
 
     TooManyContexts,
 }
@@ -95,6 +109,13 @@ impl<T: RemoteContextLoader> Error for ContextCreationError<T> {
             ContextCreationError::InvalidVocabMapping => "invalid vocab mapping",
             ContextCreationError::InvalidLanguageMapping => "invalid language mapping",
             ContextCreationError::InvalidLocalContext => "invalid local context",
+            ContextCreationError::InvalidImportValue => "invalid @import value",
+            ContextCreationError::InvalidProtectedValue => "invalid @protected value",
+            ContextCreationError::InvalidContextNullification => "invalid context nullification",
+            ContextCreationError::InvalidVersionValue => "invalid @version value",
+            ContextCreationError::ProcessingModeConflict => "processing mode conflict",
+            ContextCreationError::InvalidPropagateValue => "invalid @propagate value",
+            ContextCreationError::InvalidBaseDirection => "invalid base direction",
             ContextCreationError::TooManyContexts => "too many contexts",
         }
     }
@@ -108,8 +129,13 @@ impl<T: RemoteContextLoader> Error for ContextCreationError<T> {
     }
 }
 
+/// The amount of remote contexts that may be dereferenced while processing a
+/// single local context. This count is kept in the `remote_contexts` map, which
+/// every top-level `expand` or `compact` call starts out empty.
+const MAX_REMOTE_CONTEXTS: usize = 4;
+
 lazy_static! {
-    static ref KEYWORDS: HashSet<&'static str> = vec![
+    pub(crate) static ref KEYWORDS: HashSet<&'static str> = vec![
         "@context",
         "@id",
         "@value",
@@ -126,18 +152,221 @@ lazy_static! {
     ]
     .into_iter()
     .collect();
+
+    /// Keywords added in `json-ld-1.1`, which are still plain terms in
+    /// `json-ld-1.0`.
+    pub(crate) static ref KEYWORDS_1_1: HashSet<&'static str> =
+        vec!["@nest", "@none", "@json"].into_iter().collect();
+}
+
+/// The amount of processed contexts a `ContextCache` holds by default.
+const DEFAULT_CACHE_CAPACITY: usize = 128;
+
+/// A cache of processed local contexts, for callers that process the same
+/// context over and over, e.g. a server that expands every request against a
+/// fixed context.
+///
+/// An entry is keyed on the serialized local context, and only reused on an
+/// active context equal to the one it was processed on, base IRI included.
+/// Once the cache holds `capacity` entries, the oldest one is dropped to make
+/// room for the next. Clones share the same entries.
+///
+/// Entries aren't keyed on the `RemoteContextLoader` that loaded the remote
+/// contexts in them, so share a cache only between calls using the same
+/// loader.
+#[derive(Clone)]
+pub struct ContextCache {
+    entries: Arc<Mutex<CacheEntries>>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    contexts: HashMap<String, Vec<CachedContext>>,
+    /// The key of every entry, oldest first.
+    order: VecDeque<String>,
+}
+
+struct CachedContext {
+    active_context: Context,
+    remote_contexts: HashMap<String, Option<Value>>,
+    context: Context,
+}
+
+impl Default for ContextCache {
+    fn default() -> ContextCache {
+        ContextCache::with_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl ContextCache {
+    pub fn new() -> ContextCache {
+        ContextCache::default()
+    }
+
+    /// Creates a cache holding at most `capacity` processed contexts.
+    pub fn with_capacity(capacity: usize) -> ContextCache {
+        ContextCache {
+            entries: Arc::new(Mutex::new(CacheEntries::default())),
+            capacity,
+        }
+    }
+
+    /// Forgets every processed context.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.contexts.clear();
+        entries.order.clear();
+    }
+
+    fn get(
+        &self,
+        active_context: &Context,
+        key: &str,
+    ) -> Option<(HashMap<String, Option<Value>>, Context)> {
+        let entries = self.entries.lock().unwrap();
+
+        entries.contexts.get(key).and_then(|cached| {
+            cached
+                .iter()
+                .find(|f| f.active_context == *active_context)
+                .map(|f| (f.remote_contexts.clone(), f.context.clone()))
+        })
+    }
+
+    fn insert(
+        &self,
+        key: String,
+        active_context: Context,
+        remote_contexts: HashMap<String, Option<Value>>,
+        context: Context,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.order.len() >= self.capacity {
+            // entries for a key are pushed in order, so its oldest one is first
+            if let Some(oldest) = entries.order.pop_front() {
+                let empty = match entries.contexts.get_mut(&oldest) {
+                    Some(cached) => {
+                        cached.remove(0);
+                        cached.is_empty()
+                    }
+                    None => false,
+                };
+
+                if empty {
+                    entries.contexts.remove(&oldest);
+                }
+            }
+        }
+
+        entries.order.push_back(key.clone());
+        entries
+            .contexts
+            .entry(key)
+            .or_insert_with(Vec::new)
+            .push(CachedContext {
+                active_context,
+                remote_contexts,
+                context,
+            });
+    }
+}
+
+impl fmt::Debug for ContextCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ContextCache")
+            .field("entries", &self.entries.lock().unwrap().order.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
 }
 
 impl Context {
     pub fn new() -> Context {
         Context {
             base_iri: None,
+            original_base_iri: None,
+            processing_mode: None,
             vocabulary_mapping: None,
             language: None,
-            terms: BTreeMap::new(),
+            terms: Arc::new(BTreeMap::new()),
         }
     }
 
+    /// Whether JSON-LD 1.1 processing is enabled, through the processing mode
+    /// option. Without it, the processor sticks to JSON-LD 1.0.
+    pub(crate) fn is_json_ld_1_1(&self) -> bool {
+        self.processing_mode.as_ref().map(String::as_str) == Some("json-ld-1.1")
+    }
+
+    /// Whether `term` is a keyword in the processing mode of this context.
+    pub(crate) fn is_keyword(&self, term: &str) -> bool {
+        KEYWORDS.contains(term) || (self.is_json_ld_1_1() && KEYWORDS_1_1.contains(term))
+    }
+
+    /// Returns the terms that can be used as prefixes of compact IRIs, mapped
+    /// to the IRI they stand for.
+    ///
+    /// With `@prefix` unsupported, a term qualifies if it has no `:` in it,
+    /// isn't a reverse property, and its IRI ends in a gen-delim character,
+    /// such as `schema` for `http://schema.org/`.
+    pub fn prefixes(&self) -> BTreeMap<String, String> {
+        self.terms
+            .iter()
+            .filter(|&(term, definition)| {
+                !term.contains(":")
+                    && !definition.reverse
+                    && !definition.iri_mapping.starts_with("@")
+                    && definition
+                        .iri_mapping
+                        .ends_with(&['/', '#', ':', '?', '[', ']', '@'][..])
+            })
+            .map(|(term, definition)| (term.to_owned(), definition.iri_mapping.to_owned()))
+            .collect()
+    }
+
+    /// Returns the terms defined with `@protected`, either directly or by a
+    /// protected context. Redefining them differently, or resetting the
+    /// context with `null`, fails.
+    pub fn protected_terms(&self) -> Vec<&str> {
+        self.terms
+            .iter()
+            .filter(|&(_, definition)| definition.protected)
+            .map(|(term, _)| term.as_str())
+            .collect()
+    }
+
+    // A protected term may only be redefined to what it already is.
+    fn _insert_term(
+        &mut self,
+        term: &str,
+        definition: Term,
+        previous: Option<Term>,
+    ) -> Result<(), TermCreationError> {
+        let definition = match previous {
+            Some(ref previous) if previous.protected => {
+                let redefined = Term {
+                    protected: true,
+                    ..definition
+                };
+                if redefined != *previous {
+                    return Err(TermCreationError::ProtectedTermRedefinition);
+                }
+
+                redefined
+            }
+            _ => definition,
+        };
+
+        Arc::make_mut(&mut self.terms).insert(term.to_owned(), definition);
+        Ok(())
+    }
+
     pub(crate) fn expand_iri_mut(
         &mut self,
         val: &str,
@@ -192,9 +421,13 @@ impl Context {
                         Ok(self.vocabulary_mapping.as_ref().unwrap().to_string() + val)
                     } else if document_relative && self.base_iri.is_some() {
                         // 6
+                        // A reference the base can't resolve (say, against a `urn:`
+                        // base) is left as-is rather than panicking.
                         let base_iri = self.base_iri.as_ref().unwrap();
-                        let joined = base_iri.join(val).unwrap();
-                        Ok(joined.to_string())
+                        Ok(base_iri
+                            .join(val)
+                            .map(|joined| joined.to_string())
+                            .unwrap_or_else(|_| val.to_string()))
                     } else {
                         // 7
                         Ok(val.to_string())
@@ -240,8 +473,10 @@ impl Context {
                     } else if document_relative && self.base_iri.is_some() {
                         // 6
                         let base_iri = self.base_iri.as_ref().unwrap();
-                        let joined = base_iri.join(val).unwrap();
-                        joined.to_string()
+                        base_iri
+                            .join(val)
+                            .map(|joined| joined.to_string())
+                            .unwrap_or_else(|_| val.to_string())
                     } else {
                         // 7
                         val.to_string()
@@ -270,12 +505,12 @@ impl Context {
         defined.insert(term.to_owned(), DefineStatus::Defining);
 
         // 3
-        if KEYWORDS.contains(term) {
+        if self.is_keyword(term) {
             return Err(TermCreationError::KeywordRedefinition);
         }
 
         // 4
-        self.terms.remove(term);
+        let previous = Arc::make_mut(&mut self.terms).remove(term);
 
         // 5: implicit???
 
@@ -290,19 +525,30 @@ impl Context {
             // 6, todo @id: null
             Value::Null => {
                 // XXX really bad hack to avoid @vocab???
-                self.terms.insert(
-                    term.to_owned(),
+                self._insert_term(
+                    term,
                     Term {
                         type_mapping: None,
                         iri_mapping: term.to_owned(),
                         reverse: false,
                         container_mapping: None,
                         language_mapping: None,
+                        nest_value: None,
+                        protected: false,
                     },
-                );
+                )?;
             }
             Value::String(_) => unreachable!(),
             Value::Object(mut map) => {
+                // @protected, which JSON-LD 1.0 doesn't know about
+                let protected = match map.remove("@protected") {
+                    Some(Value::Bool(protected)) => protected && self.is_json_ld_1_1(),
+                    Some(_) if self.is_json_ld_1_1() => {
+                        return Err(TermCreationError::InvalidProtectedValue)
+                    }
+                    _ => false,
+                };
+
                 // 10, 10.3
                 let type_mapping = if let Some(at_type) = map.remove("@type") {
                     match at_type {
@@ -310,7 +556,8 @@ impl Context {
                             // 10.2
                             let res =
                                 self.expand_iri_mut(&string, false, true, defined, context)?;
-                            if !res.contains(":") && res != "@id" && res != "@vocab" {
+                            let is_json = self.is_json_ld_1_1() && res == "@json";
+                            if !res.contains(":") && res != "@id" && res != "@vocab" && !is_json {
                                 return Err(TermCreationError::InvalidTypeMapping);
                             }
                             Some(res)
@@ -364,16 +611,18 @@ impl Context {
                     // 11.6
                     defined.insert(term.to_owned(), DefineStatus::Defined);
 
-                    self.terms.insert(
-                        term.to_owned(),
+                    self._insert_term(
+                        term,
                         Term {
                             type_mapping: type_mapping,
                             iri_mapping: reverse_map.unwrap(),
                             reverse: true,
                             container_mapping: container_mapping,
                             language_mapping: None,
+                            nest_value: None,
+                            protected: protected,
                         },
-                    );
+                    )?;
                 } else {
                     // 13
                     let mut iri_mapping = if let Some(at_id) = map.remove("@id") {
@@ -443,6 +692,18 @@ impl Context {
 
                     // 16, 16.2
                     let container_mapping = if let Some(at_container) = map.remove("@container") {
+                        // 1.1 also allows the container to be spelled as a one-element array
+                        let at_container = match at_container {
+                            Value::Array(mut arr) => {
+                                if self.is_json_ld_1_1() && arr.len() == 1 {
+                                    arr.pop().unwrap()
+                                } else {
+                                    return Err(TermCreationError::InvalidContainerMapping);
+                                }
+                            }
+                            other => other,
+                        };
+
                         match at_container {
                             Value::String(string) => {
                                 // 16.1
@@ -450,6 +711,7 @@ impl Context {
                                     || string == "@set"
                                     || string == "@index"
                                     || string == "@language"
+                                    || (string == "@id" && self.is_json_ld_1_1())
                                 {
                                     Some(string)
                                 } else {
@@ -480,18 +742,41 @@ impl Context {
                         }
                     };
 
+                    // @nest: either the keyword itself, or a term aliasing it
+                    let nest = if self.is_json_ld_1_1() {
+                        map.remove("@nest")
+                    } else {
+                        None
+                    };
+                    let nest_value = if let Some(nest) = nest {
+                        match nest {
+                            Value::String(string) => {
+                                if string != "@nest" && string.starts_with("@") {
+                                    return Err(TermCreationError::InvalidNestValue);
+                                }
+
+                                Some(string)
+                            }
+                            _ => return Err(TermCreationError::InvalidNestValue),
+                        }
+                    } else {
+                        None
+                    };
+
                     // 18
                     defined.insert(term.to_string(), DefineStatus::Defined);
-                    self.terms.insert(
-                        term.to_string(),
+                    self._insert_term(
+                        term,
                         Term {
                             type_mapping: type_mapping,
                             iri_mapping: iri_mapping.unwrap(),
                             reverse: false,
                             container_mapping: container_mapping,
                             language_mapping: language_mapping,
+                            nest_value: nest_value,
+                            protected: protected,
                         },
-                    );
+                    )?;
                 }
             }
             // 8
@@ -501,11 +786,78 @@ impl Context {
         Ok(())
     }
 
+    /// Applies a context's `@version` entry. Only 1.1 is valid, and it turns
+    /// on json-ld-1.1, unless json-ld-1.0 was asked for.
+    fn _set_version<T: RemoteContextLoader>(
+        &mut self,
+        version: &Value,
+    ) -> Result<(), ContextCreationError<T>> {
+        if version.as_f64() != Some(1.1) {
+            return Err(ContextCreationError::InvalidVersionValue);
+        }
+
+        if self.processing_mode.as_ref().map(String::as_str) == Some("json-ld-1.0") {
+            return Err(ContextCreationError::ProcessingModeConflict);
+        }
+
+        self.processing_mode = Some("json-ld-1.1".to_owned());
+        Ok(())
+    }
+
+    /// Processes `local_context` on top of this context, starting a fresh
+    /// chain of remote contexts, and goes through `cache` if there is one.
+    #[async(boxed_send)]
+    pub(crate) fn process_context_cached<T: RemoteContextLoader>(
+        self,
+        local_context: Value,
+        cache: Option<ContextCache>,
+    ) -> Result<(HashMap<String, Option<Value>>, Context), ContextCreationError<T>> {
+        let cache = match cache {
+            Some(cache) => cache,
+            None => return await!(self.process_context::<T>(local_context, HashMap::new())),
+        };
+
+        let key = local_context.to_string();
+        if let Some(cached) = cache.get(&self, &key) {
+            return Ok(cached);
+        }
+
+        let active_context = self.clone();
+        let (remote_contexts, context) =
+            await!(self.process_context::<T>(local_context, HashMap::new()))?;
+        cache.insert(
+            key,
+            active_context,
+            remote_contexts.clone(),
+            context.clone(),
+        );
+
+        Ok((remote_contexts, context))
+    }
+
+    /// Processes `local_context` on top of this context.
+    ///
+    /// `remote_contexts` holds the remote contexts dereferenced so far, and is
+    /// returned alongside the new context. Pass an empty map to start a fresh
+    /// chain; the `TooManyContexts` limit applies per chain.
     #[async(boxed_send)]
     pub fn process_context<T: RemoteContextLoader>(
+        self,
+        local_context: Value,
+        remote_contexts: HashMap<String, Option<Value>>,
+    ) -> Result<(HashMap<String, Option<Value>>, Context), ContextCreationError<T>> {
+        await!(self._process_context::<T>(local_context, remote_contexts, None))
+    }
+
+    /// Processes `local_context`, which was loaded from `context_url` if it is a
+    /// remote context. Relative context references in it are resolved against
+    /// that URL, or against the base IRI for a local context.
+    #[async(boxed_send)]
+    fn _process_context<T: RemoteContextLoader>(
         mut self,
         local_context: Value,
         mut remote_contexts: HashMap<String, Option<Value>>,
+        context_url: Option<Url>,
     ) -> Result<(HashMap<String, Option<Value>>, Context), ContextCreationError<T>> {
         // 2
         let local_context = match local_context {
@@ -518,22 +870,46 @@ impl Context {
             match context {
                 // 3.1
                 Value::Null => {
+                    if self.terms.values().any(|definition| definition.protected) {
+                        return Err(ContextCreationError::InvalidContextNullification);
+                    }
+
+                    // the original base IRI and processing mode survive the
+                    // reset, everything else (terms, @vocab, @language, and
+                    // what @base did) starts over
+                    let base_iri = match self.original_base_iri.take() {
+                        Some(original) => original,
+                        None => self.base_iri.take(),
+                    };
+                    let processing_mode = self.processing_mode.take();
                     self = Context::new();
-                    self.base_iri = self.base_iri.clone();
+                    self.base_iri = base_iri;
+                    self.processing_mode = processing_mode;
                 }
 
                 // 3.2
                 Value::String(val) => {
-                    if remote_contexts.len() > 4 {
+                    // 3.2.1
+                    let val = match context_url.as_ref().or(self.base_iri.as_ref()) {
+                        Some(base_iri) => base_iri
+                            .join(&val)
+                            .map(|joined| joined.to_string())
+                            .unwrap_or(val),
+                        None => val,
+                    };
+
+                    if remote_contexts.len() > MAX_REMOTE_CONTEXTS {
                         return Err(ContextCreationError::TooManyContexts);
                     }
 
                     match remote_contexts.get(&val).cloned() {
                         Some(None) => return Err(ContextCreationError::RecursiveContextInclusion),
                         Some(Some(context)) => {
-                            let (rc, s) = await!(
-                                self.process_context::<T>(context.clone(), remote_contexts)
-                            )?;
+                            let (rc, s) = await!(self._process_context::<T>(
+                                context.clone(),
+                                remote_contexts,
+                                Url::parse(&val).ok(),
+                            ))?;
                             remote_contexts = rc;
                             remote_contexts.insert(val, Some(context));
 
@@ -552,9 +928,11 @@ impl Context {
                                     .unwrap_or_else(|| Value::Object(JsonMap::new()));
 
                                 // 3.2.4
-                                let (rc, s) = await!(
-                                    self.process_context::<T>(context.clone(), remote_contexts)
-                                )?;
+                                let (rc, s) = await!(self._process_context::<T>(
+                                    context.clone(),
+                                    remote_contexts,
+                                    Url::parse(&val).ok(),
+                                ))?;
                                 remote_contexts = rc;
                                 remote_contexts.insert(val, Some(context));
 
@@ -566,9 +944,74 @@ impl Context {
                     }
                 }
                 Value::Object(mut map) => {
+                    if let Some(version) = map.remove("@version") {
+                        self._set_version::<T>(&version)?;
+                    }
+
+                    // 1.1 @import: the imported context is merged in under the
+                    // local entries. Unlike the spec, an imported context may
+                    // import another; the chain is bounded and cycles rejected.
+                    let mut imported = Vec::new();
+                    if self.is_json_ld_1_1() {
+                        let mut import = map.remove("@import");
+                        let mut import_url = context_url.clone();
+
+                        while let Some(value) = import.take() {
+                            let val = match value {
+                                Value::String(val) => {
+                                    match import_url.as_ref().or(self.base_iri.as_ref()) {
+                                        Some(base_iri) => base_iri
+                                            .join(&val)
+                                            .map(|joined| joined.to_string())
+                                            .unwrap_or(val),
+                                        None => val,
+                                    }
+                                }
+                                _ => return Err(ContextCreationError::InvalidImportValue),
+                            };
+
+                            if imported.iter().any(|&(ref url, _)| *url == val) {
+                                return Err(ContextCreationError::RecursiveContextInclusion);
+                            }
+
+                            if imported.len() >= MAX_REMOTE_CONTEXTS {
+                                return Err(ContextCreationError::TooManyContexts);
+                            }
+
+                            let dereferenced = await!(T::load_context(val.to_owned()))
+                                .map_err(|e| ContextCreationError::RemoteContextError(e))?;
+
+                            let mut context = match dereferenced {
+                                Value::Object(mut obj) => match obj.remove("@context") {
+                                    Some(Value::Object(context)) => context,
+                                    _ => return Err(ContextCreationError::RemoteContextNoObject),
+                                },
+                                _ => return Err(ContextCreationError::RemoteContextNoObject),
+                            };
+
+                            // an import in the imported context is relative to it
+                            import_url = Url::parse(&val).ok();
+                            imported.push((val, Value::Object(context.clone())));
+
+                            import = context.remove("@import");
+                            for (key, value) in context {
+                                map.entry(key).or_insert(value);
+                            }
+                        }
+
+                        // imported contexts usually carry "@version": 1.1 themselves
+                        if let Some(version) = map.remove("@version") {
+                            self._set_version::<T>(&version)?;
+                        }
+                    }
+
                     let base = map.remove("@base");
                     if base != None && remote_contexts.is_empty() {
                         let value = base.unwrap();
+                        if self.original_base_iri.is_none() {
+                            self.original_base_iri = Some(self.base_iri.clone());
+                        }
+
                         match value {
                             Value::Null => self.base_iri = None,
                             Value::String(val) => {
@@ -588,13 +1031,20 @@ impl Context {
                         }
                     };
 
+                    // imported contexts are remote contexts too; they are only
+                    // recorded now, since @base is ignored once there are any
+                    for (url, context) in imported {
+                        remote_contexts.insert(url, Some(context));
+                    }
+
                     // 3.5
                     if let Some(vocab) = map.remove("@vocab") {
                         match vocab {
                             Value::Null => self.vocabulary_mapping = None,
                             Value::String(data) => {
-                                // xxx proper absolute iri processor
-                                self.vocabulary_mapping = Some(data);
+                                // a relative @vocab is resolved against the base IRI, and
+                                // "" against the previous vocabulary mapping if there is one
+                                self.vocabulary_mapping = Some(self.expand_iri(&data, true, true));
                             }
                             _ => return Err(ContextCreationError::InvalidVocabMapping),
                         }
@@ -611,6 +1061,61 @@ impl Context {
                         }
                     }
 
+                    // a protected context protects every term in it that
+                    // doesn't opt out with "@protected": false
+                    if self.is_json_ld_1_1() {
+                        match map.remove("@protected") {
+                            Some(Value::Bool(true)) => {
+                                for (key, definition) in map.iter_mut() {
+                                    if key.starts_with("@") {
+                                        continue;
+                                    }
+
+                                    if let Value::String(id) = definition.clone() {
+                                        let mut expanded = JsonMap::new();
+                                        expanded.insert("@id".to_owned(), Value::String(id));
+                                        *definition = Value::Object(expanded);
+                                    }
+
+                                    if let Value::Object(ref mut definition) = *definition {
+                                        if !definition.contains_key("@protected") {
+                                            definition
+                                                .insert("@protected".to_owned(), Value::Bool(true));
+                                        }
+                                    }
+                                }
+                            }
+                            Some(Value::Bool(false)) | None => {}
+                            Some(_) => return Err(ContextCreationError::InvalidProtectedValue),
+                        }
+                    }
+
+                    // @propagate and @direction are validated, but have no effect yet
+                    if self.is_json_ld_1_1() {
+                        match map.remove("@propagate") {
+                            Some(Value::Bool(_)) | None => {}
+                            Some(_) => return Err(ContextCreationError::InvalidPropagateValue),
+                        }
+
+                        match map.remove("@direction") {
+                            Some(Value::Null) | None => {}
+                            Some(Value::String(ref direction))
+                                if direction == "ltr" || direction == "rtl" => {}
+                            Some(_) => return Err(ContextCreationError::InvalidBaseDirection),
+                        }
+                    }
+
+                    // none of the 1.1 entries define a term; in 1.0 they are ignored
+                    for key in &[
+                        "@version",
+                        "@import",
+                        "@protected",
+                        "@propagate",
+                        "@direction",
+                    ] {
+                        map.remove(*key);
+                    }
+
                     let mut defined: HashMap<String, DefineStatus> = HashMap::new();
 
                     while !map.is_empty() {
@@ -628,3 +1133,299 @@ impl Context {
         Ok((remote_contexts, self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+    use test_util::{json, StubLoader};
+
+    fn cached(cache: &ContextCache, context: &str) {
+        let context = json(context);
+
+        Context::new()
+            .process_context_cached::<StubLoader>(context, Some(cache.clone()))
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn cache_drops_the_oldest_entry_when_full() {
+        let cache = ContextCache::with_capacity(2);
+        let a = r#"{"a":"http://example.com/a"}"#;
+        let b = r#"{"b":"http://example.com/b"}"#;
+        let c = r#"{"c":"http://example.com/c"}"#;
+
+        cached(&cache, a);
+        cached(&cache, b);
+        cached(&cache, c);
+
+        let ctx = Context::new();
+        assert!(cache.get(&ctx, a).is_none());
+        assert!(cache.get(&ctx, b).is_some());
+        assert!(cache.get(&ctx, c).is_some());
+    }
+
+    fn process(context: &str) -> Result<Context, ContextCreationError<StubLoader>> {
+        Context::new()
+            .process_context::<StubLoader>(json(context), HashMap::new())
+            .wait()
+            .map(|(_, ctx)| ctx)
+    }
+
+    fn iri_mapping<'a>(ctx: &'a Context, term: &str) -> Option<&'a str> {
+        ctx.terms.get(term).map(|term| term.iri_mapping.as_str())
+    }
+
+    #[test]
+    fn imports_are_followed_two_levels_deep() {
+        let ctx =
+            process(r#"{"@version": 1.1, "@import": "http://example.com/import/outer.jsonld"}"#)
+                .unwrap();
+
+        assert_eq!(iri_mapping(&ctx, "o"), Some("http://example.com/o"));
+        assert_eq!(iri_mapping(&ctx, "i"), Some("http://example.com/i"));
+    }
+
+    #[test]
+    fn a_context_importing_itself_is_rejected() {
+        match process(r#"{"@version": 1.1, "@import": "http://example.com/import/self.jsonld"}"#) {
+            Err(ContextCreationError::RecursiveContextInclusion) => (),
+            other => panic!("expected RecursiveContextInclusion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_chains_are_bounded() {
+        match process(r#"{"@version": 1.1, "@import": "http://example.com/chain/0.jsonld"}"#) {
+            Err(ContextCreationError::TooManyContexts) => (),
+            other => panic!("expected TooManyContexts, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn terms_use_the_vocab_set_before_them_in_an_array() {
+        let ctx = process(
+            r#"[
+                {"@vocab": "http://example.com/old/", "a": "term-a"},
+                null,
+                {"@vocab": "http://example.com/new/"},
+                {"b": "term-b"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.vocabulary_mapping.as_ref().map(String::as_str),
+            Some("http://example.com/new/")
+        );
+        assert_eq!(iri_mapping(&ctx, "a"), None);
+        assert_eq!(
+            iri_mapping(&ctx, "b"),
+            Some("http://example.com/new/term-b")
+        );
+    }
+
+    #[test]
+    fn a_null_context_restores_the_original_base() {
+        let mut ctx = Context::new();
+        ctx.base_iri = Some(Url::parse("http://example.com/doc").unwrap());
+
+        let (_, ctx) = ctx
+            .process_context::<StubLoader>(
+                json(r#"[{"@base": "http://example.com/other/"}, null]"#),
+                HashMap::new(),
+            )
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            ctx.base_iri,
+            Some(Url::parse("http://example.com/doc").unwrap())
+        );
+    }
+
+    fn vocabulary_mapping(context: &str) -> Option<String> {
+        process(context).unwrap().vocabulary_mapping
+    }
+
+    #[test]
+    fn relative_vocabs_resolve_against_the_base() {
+        assert_eq!(
+            vocabulary_mapping(r#"{"@base": "http://example.com/doc/", "@vocab": "terms/"}"#),
+            Some("http://example.com/doc/terms/".to_owned())
+        );
+    }
+
+    #[test]
+    fn an_empty_vocab_means_the_base_or_the_previous_vocab() {
+        assert_eq!(
+            vocabulary_mapping(r#"{"@base": "http://example.com/doc/", "@vocab": ""}"#),
+            Some("http://example.com/doc/".to_owned())
+        );
+        assert_eq!(
+            vocabulary_mapping(
+                r#"[
+                    {"@base": "http://example.com/doc/", "@vocab": "http://example.com/v#"},
+                    {"@vocab": ""}
+                ]"#
+            ),
+            Some("http://example.com/v#".to_owned())
+        );
+    }
+
+    fn process_in(
+        processing_mode: &str,
+        context: &str,
+    ) -> Result<Context, ContextCreationError<StubLoader>> {
+        let mut ctx = Context::new();
+        ctx.processing_mode = Some(processing_mode.to_owned());
+
+        ctx.process_context::<StubLoader>(json(context), HashMap::new())
+            .wait()
+            .map(|(_, ctx)| ctx)
+    }
+
+    const RESERVED_ENTRIES: &'static [&'static str] = &[
+        r#""@protected": true"#,
+        r#""@propagate": true"#,
+        r#""@import": "http://example.com/import/inner.jsonld""#,
+        r#""@direction": "ltr""#,
+    ];
+
+    #[test]
+    fn reserved_entries_are_not_defined_as_terms_in_json_ld_1_1() {
+        let ctx = process(r#"{"@version": 1.1, "t": "http://example.com/t"}"#).unwrap();
+        assert_eq!(ctx.terms.keys().collect::<Vec<_>>(), vec!["t"]);
+
+        for entry in RESERVED_ENTRIES {
+            let context = format!(r#"{{{}, "t": "http://example.com/t"}}"#, entry);
+            let ctx = process_in("json-ld-1.1", &context).unwrap();
+
+            assert!(
+                ctx.terms.keys().all(|term| !term.starts_with("@")),
+                "{}",
+                entry
+            );
+            assert_eq!(iri_mapping(&ctx, "t"), Some("http://example.com/t"));
+        }
+    }
+
+    #[test]
+    fn reserved_entries_are_ignored_in_json_ld_1_0() {
+        for entry in RESERVED_ENTRIES {
+            let context = format!(r#"{{{}, "t": "http://example.com/t"}}"#, entry);
+            let ctx = process_in("json-ld-1.0", &context).unwrap();
+
+            assert_eq!(ctx.terms.keys().collect::<Vec<_>>(), vec!["t"], "{}", entry);
+        }
+
+        match process_in("json-ld-1.0", r#"{"@version": 1.1}"#) {
+            Err(ContextCreationError::ProcessingModeConflict) => (),
+            other => panic!("expected ProcessingModeConflict, got {:?}", other),
+        }
+    }
+
+    const PROTECTED_CONTEXT: &'static str = r#"{
+        "@version": 1.1,
+        "@protected": true,
+        "a": "http://example.com/a",
+        "b": {"@id": "http://example.com/b", "@protected": false}
+    }"#;
+
+    #[test]
+    fn protected_terms_lists_the_terms_a_protected_context_defines() {
+        let ctx = process(PROTECTED_CONTEXT).unwrap();
+
+        assert_eq!(ctx.protected_terms(), vec!["a"]);
+    }
+
+    #[test]
+    fn protected_terms_cannot_be_redefined() {
+        let ctx = process(PROTECTED_CONTEXT).unwrap();
+
+        // the same definition again is fine
+        let ctx = ctx
+            .process_context::<StubLoader>(json(r#"{"a": "http://example.com/a"}"#), HashMap::new())
+            .wait()
+            .unwrap()
+            .1;
+
+        match ctx
+            .process_context::<StubLoader>(
+                json(r#"{"a": "http://example.com/other"}"#),
+                HashMap::new(),
+            )
+            .wait()
+        {
+            Err(ContextCreationError::InvalidTerm(
+                TermCreationError::ProtectedTermRedefinition,
+            )) => (),
+            other => panic!("expected ProtectedTermRedefinition, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn prefixes_lists_the_terms_usable_as_prefixes() {
+        let ctx = process(
+            r#"{
+                "schema": "http://schema.org/",
+                "name": "http://schema.org/name",
+                "parent": {"@reverse": "http://example.com/children/"}
+            }"#,
+        )
+        .unwrap();
+
+        let mut expected = BTreeMap::new();
+        expected.insert("schema".to_owned(), "http://schema.org/".to_owned());
+        assert_eq!(ctx.prefixes(), expected);
+    }
+
+    #[test]
+    fn relative_bases_resolve_against_the_previous_base() {
+        let mut ctx = Context::new();
+        ctx.base_iri = Some(Url::parse("http://example.com/dir/doc").unwrap());
+
+        let (_, ctx) = ctx
+            .process_context::<StubLoader>(
+                json(r#"[{"@base": "sub/"}, {"@base": "deeper/"}]"#),
+                HashMap::new(),
+            )
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            ctx.base_iri,
+            Some(Url::parse("http://example.com/dir/sub/deeper/").unwrap())
+        );
+    }
+
+    #[test]
+    fn relative_bases_without_a_previous_base_are_rejected() {
+        match process(r#"{"@base": "sub/"}"#) {
+            Err(ContextCreationError::InvalidBaseIRI) => (),
+            other => panic!("expected InvalidBaseIRI, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn explicit_ids_win_over_the_vocab() {
+        let ctx = process(
+            r#"{
+                "@vocab": "http://example.com/vocab/",
+                "explicit": {"@id": "http://example.com/explicit"},
+                "implicit": {"@type": "@id"}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            iri_mapping(&ctx, "explicit"),
+            Some("http://example.com/explicit")
+        );
+        assert_eq!(
+            iri_mapping(&ctx, "implicit"),
+            Some("http://example.com/vocab/implicit")
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use super::context::{Context, Term};
-use super::creation::ContextCreationError;
-use super::RemoteContextLoader;
+use super::creation::{ContextCache, ContextCreationError};
+use super::{RelativeIriHandling, RemoteContextLoader};
 use serde_json::{Map, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
 
 use futures::prelude::{await, *};
 
@@ -23,7 +25,8 @@ pub enum ExpansionError<T: RemoteContextLoader> {
     /// Value inside a `@language` map is not a string
     InvalidLanguageMapValue,
 
-    /// Value inside `@language` inside a value object is not a string.
+    /// Value inside `@language` inside a value object is not a string, or the
+    /// `@value` it is attached to isn't.
     InvalidLanguageTaggedString,
 
     /// Value in `@index` is not a string.
@@ -32,7 +35,7 @@ pub enum ExpansionError<T: RemoteContextLoader> {
     /// Object inside reverse property is value/list object.
     InvalidReversePropertyValue,
 
-    /// `@is` value is not a string.
+    /// `@id` value is not a string, such as an array or a number.
     InvalidIdValue,
 
     /// Value object contains invalid keys, or both `@type` and `@language`.
@@ -47,6 +50,9 @@ pub enum ExpansionError<T: RemoteContextLoader> {
     /// Object contains keys other than `@list` and `@index`.
     InvalidListObject,
 
+    /// A `@set` object is used as an item of a list.
+    InvalidSetOrListObject,
+
     /// Object's `@type` is neither a string nor a list of string.
     InvalidTypeValue,
 
@@ -56,6 +62,23 @@ pub enum ExpansionError<T: RemoteContextLoader> {
     /// `@reverse` is not an object.
     InvalidReverseValue,
 
+    /// `@nest` value is not an object, or contains a value object.
+    InvalidNestValue,
+
+    /// A key expands to neither a keyword nor an IRI, in strict mode.
+    UndefinedTerm,
+
+    /// A key expands to a keyword that is only valid inside a context, in
+    /// strict mode.
+    InvalidKeyword,
+
+    /// An IRI is still relative after expansion, and relative IRIs were set
+    /// to be an error.
+    RelativeIRI,
+
+    /// The document nests arrays and objects deeper than the maximum depth.
+    MaxDepthExceeded,
+
     /// An error when parsing the context.
     ContextExpansionError(ContextCreationError<T>),
 }
@@ -86,9 +109,15 @@ impl<T: RemoteContextLoader> Error for ExpansionError<T> {
             ExpansionError::InvalidTypedValue => "invalid typed value",
             ExpansionError::InvalidSetObject => "invalid set object",
             ExpansionError::InvalidListObject => "invalid list object",
+            ExpansionError::InvalidSetOrListObject => "invalid set or list object",
             ExpansionError::InvalidTypeValue => "invalid @type value",
             ExpansionError::InvalidValueObjectValue => "invalid value object value",
             ExpansionError::InvalidReverseValue => "invalid @reverse value",
+            ExpansionError::InvalidNestValue => "invalid @nest value",
+            ExpansionError::UndefinedTerm => "undefined term",
+            ExpansionError::InvalidKeyword => "invalid keyword",
+            ExpansionError::RelativeIRI => "relative IRI",
+            ExpansionError::MaxDepthExceeded => "maximum depth exceeded",
             ExpansionError::ContextExpansionError(_) => "Failed to expand context",
         }
     }
@@ -101,10 +130,127 @@ impl<T: RemoteContextLoader> Error for ExpansionError<T> {
     }
 }
 
+/// How deep arrays and objects may nest in a document being expanded, unless
+/// set otherwise. Every level takes a recursive call, so this keeps
+/// adversarial documents from exhausting the stack.
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Flags that change how expansion behaves, passed down through every step.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExpansionOptions {
+    /// Fail on keys that expand to neither a keyword nor an IRI, instead of
+    /// dropping them.
+    pub strict: bool,
+
+    /// Sort the `@type` values of node objects, instead of keeping them in
+    /// document order.
+    pub ordered: bool,
+
+    /// What to do with IRIs that stay relative.
+    pub relative_iri_handling: RelativeIriHandling,
+
+    /// If set, collects every remote context that gets dereferenced, keyed
+    /// on its URL.
+    pub remote_contexts: Option<Arc<Mutex<HashMap<String, Value>>>>,
+
+    /// If set, embedded contexts are looked up in and added to this cache.
+    pub context_cache: Option<ContextCache>,
+
+    /// How deep the document may nest, `DEFAULT_MAX_DEPTH` if unset.
+    pub max_depth: Option<usize>,
+
+    /// How deep the element being expanded is nested.
+    pub depth: usize,
+}
+
+impl ExpansionOptions {
+    /// Records the remote contexts that processing a local context loaded.
+    pub(crate) fn record_remote_contexts(&self, remote_contexts: HashMap<String, Option<Value>>) {
+        if let Some(ref collected) = self.remote_contexts {
+            let mut collected = collected.lock().unwrap();
+
+            for (url, context) in remote_contexts {
+                if let Some(context) = context {
+                    collected.insert(url, context);
+                }
+            }
+        }
+    }
+}
+
+fn _is_absolute_iri(iri: &str) -> bool {
+    match iri.find(":") {
+        Some(loc) => {
+            let scheme = &iri[..loc];
+
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+/// Applies `handling` to an expanded IRI, returning `None` if it is to be
+/// dropped. Keywords and blank node identifiers are never relative.
+fn _check_relative_iri<T: RemoteContextLoader>(
+    iri: String,
+    handling: RelativeIriHandling,
+) -> Result<Option<String>, ExpansionError<T>> {
+    if iri.starts_with("@") || iri.starts_with("_:") || _is_absolute_iri(&iri) {
+        return Ok(Some(iri));
+    }
+
+    match handling {
+        RelativeIriHandling::Keep => Ok(Some(iri)),
+        RelativeIriHandling::Drop => Ok(None),
+        RelativeIriHandling::Error => Err(ExpansionError::RelativeIRI),
+    }
+}
+
+/// Whether one of the items of `value`, or of a list nested in it, is a
+/// `@set` object. A `@set` object as the value itself is fine, and unwraps
+/// into the list.
+fn _contains_set_object(active_context: &Context, value: &Value) -> bool {
+    match *value {
+        Value::Array(ref arr) => arr.iter().any(|item| match *item {
+            Value::Array(_) => _contains_set_object(active_context, item),
+            Value::Object(ref obj) => obj
+                .keys()
+                .any(|key| active_context.expand_iri(key, false, true) == "@set"),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
 fn _array_or_list_object(elem: &Value) -> bool {
     elem.is_array() || (elem.is_object() && elem.as_object().unwrap().contains_key("@list"))
 }
 
+/// Wraps the arrays directly inside a `@list` value in list objects of their
+/// own, as lists of lists are allowed in JSON-LD 1.1.
+fn _nest_inner_lists(value: Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| {
+                    if item.is_array() {
+                        let mut map = Map::new();
+                        map.insert("@list".to_owned(), item);
+                        Value::Object(map)
+                    } else {
+                        item
+                    }
+                })
+                .collect(),
+        ),
+        _ => value,
+    }
+}
+
 impl Context {
     fn _expand_value(&self, active_property: &str, elem: Value) -> Value {
         let mut resmap = Map::new();
@@ -153,7 +299,13 @@ impl Context {
         active_context: Context,
         active_property: Option<String>,
         elem: Value,
+        mut options: ExpansionOptions,
     ) -> Result<Value, ExpansionError<T>> {
+        options.depth += 1;
+        if options.depth > options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH) {
+            return Err(ExpansionError::MaxDepthExceeded);
+        }
+
         match elem {
             // 1
             Value::Null => Ok(Value::Null),
@@ -162,27 +314,35 @@ impl Context {
                 let mut res = Vec::new();
                 for item in arr {
                     // 3.2.1
-                    let expanded_item = await!(Context::_expand::<T>(
+                    let mut expanded_item = await!(Context::_expand::<T>(
                         active_context.clone(),
                         active_property.clone(),
-                        item
+                        item,
+                        options.clone()
                     ))?;
 
                     // 3.2.2
                     if _array_or_list_object(&expanded_item) {
                         if let Some(string) = active_property.clone() {
-                            if string == "@list" {
-                                return Err(ExpansionError::ListOfLists);
-                            }
-
-                            if let Some(data) = active_context
-                                .terms
-                                .get(&string)
-                                .and_then(|a| a.container_mapping.as_ref())
-                            {
-                                if data == "@list" {
+                            let inside_list = string == "@list"
+                                || active_context
+                                    .terms
+                                    .get(&string)
+                                    .and_then(|a| a.container_mapping.as_ref())
+                                    .map(String::as_str)
+                                    == Some("@list");
+
+                            if inside_list {
+                                if !active_context.is_json_ld_1_1() {
                                     return Err(ExpansionError::ListOfLists);
                                 }
+
+                                // 1.1 keeps a nested array as a nested list
+                                if expanded_item.is_array() {
+                                    let mut map = Map::new();
+                                    map.insert("@list".to_owned(), expanded_item);
+                                    expanded_item = Value::Object(map);
+                                }
                             }
                         }
                     }
@@ -208,9 +368,13 @@ impl Context {
                 // 5
                 let active_context = if map.contains_key("@context") {
                     // ugly hack to make the active_context survive
-                    let (_, ctx) = await!(active_context
-                        .process_context::<T>(map.remove("@context").unwrap(), HashMap::new()))
+                    let (remote_contexts, ctx) = await!(active_context
+                        .process_context_cached::<T>(
+                            map.remove("@context").unwrap(),
+                            options.context_cache.clone()
+                        ))
                     .map_err(|e| ExpansionError::ContextExpansionError(e))?;
+                    options.record_remote_contexts(remote_contexts);
 
                     ctx
                 } else {
@@ -220,8 +384,23 @@ impl Context {
                 // 6
                 let mut result: Map<String, Value> = Map::new();
 
+                // properties inside @nest objects are queued up behind the
+                // ones of the element itself, and expanded as if they were
+                // set directly on it.
+                let mut entries: VecDeque<(String, Value)> = map.into_iter().collect();
+
+                // a value object typed @json may hold any JSON as its @value
+                let is_json_literal = active_context.is_json_ld_1_1()
+                    && entries.iter().any(|&(ref key, ref value)| {
+                        active_context.expand_iri(key, false, true) == "@type"
+                            && value.as_str() == Some("@json")
+                    });
+
+                // set when a relative @id is dropped, see below
+                let mut dropped_id = false;
+
                 // 7
-                for (key, mut value) in map {
+                while let Some((key, mut value)) = entries.pop_front() {
                     // 7.1
                     if key == "@context" {
                         continue;
@@ -232,9 +411,19 @@ impl Context {
 
                     // 7.3
                     if !prop.contains(":") && !prop.starts_with("@") {
+                        // a term the context maps to null is dropped on purpose
+                        if options.strict && !active_context.terms.contains_key(&key) {
+                            return Err(ExpansionError::UndefinedTerm);
+                        }
+
                         continue;
                     }
 
+                    if options.strict && prop.starts_with("@") && !active_context.is_keyword(&prop)
+                    {
+                        return Err(ExpansionError::UndefinedTerm);
+                    }
+
                     // 7.4
                     if prop.starts_with("@") {
                         let expanded_value: Value;
@@ -253,9 +442,16 @@ impl Context {
                             // 7.4.3
                             "@id" => {
                                 if let Value::String(idval) = value {
-                                    expanded_value = Value::String(
+                                    match _check_relative_iri::<T>(
                                         active_context.expand_iri(&idval, true, false),
-                                    )
+                                        options.relative_iri_handling,
+                                    )? {
+                                        Some(idval) => expanded_value = Value::String(idval),
+                                        None => {
+                                            dropped_id = true;
+                                            continue;
+                                        }
+                                    }
                                 } else {
                                     return Err(ExpansionError::InvalidIdValue);
                                 }
@@ -264,22 +460,35 @@ impl Context {
                             // 7.4.4
                             "@type" => {
                                 expanded_value = match value {
-                                    Value::String(typeval) => Value::String(
+                                    Value::String(typeval) => match _check_relative_iri::<T>(
                                         active_context.expand_iri(&typeval, true, true),
-                                    ),
+                                        options.relative_iri_handling,
+                                    )? {
+                                        Some(typeval) => Value::String(typeval),
+                                        None => continue,
+                                    },
                                     Value::Array(typevals) => {
                                         let mut result = Vec::new();
+                                        let count = typevals.len();
 
                                         for a in typevals {
                                             if let Value::String(ref aval) = a {
-                                                result.push(Value::String(
+                                                if let Some(aval) = _check_relative_iri::<T>(
                                                     active_context.expand_iri(aval, true, true),
-                                                ));
+                                                    options.relative_iri_handling,
+                                                )? {
+                                                    result.push(Value::String(aval));
+                                                }
                                             } else {
                                                 return Err(ExpansionError::InvalidTypeValue);
                                             }
                                         }
 
+                                        // every type was dropped, as opposed to "@type": []
+                                        if result.is_empty() && count > 0 {
+                                            continue;
+                                        }
+
                                         Value::Array(result)
                                     }
                                     _ => return Err(ExpansionError::InvalidTypeValue),
@@ -291,17 +500,15 @@ impl Context {
                                 expanded_value = await!(Context::_expand::<T>(
                                     active_context.clone(),
                                     Some(prop.to_owned()),
-                                    value
+                                    value,
+                                    options.clone()
                                 ))?
                             }
 
                             // 7.4.6
                             "@value" => {
                                 expanded_value = match value {
-                                    Value::Object(_) => {
-                                        return Err(ExpansionError::InvalidValueObjectValue)
-                                    }
-                                    Value::Array(_) => {
+                                    Value::Object(_) | Value::Array(_) if !is_json_literal => {
                                         return Err(ExpansionError::InvalidValueObjectValue)
                                     }
                                     _ => value,
@@ -334,16 +541,28 @@ impl Context {
                                     continue;
                                 }
 
+                                if _contains_set_object(&active_context, &value) {
+                                    return Err(ExpansionError::InvalidSetOrListObject);
+                                }
+
                                 // 7.4.9.2
+                                let value = if active_context.is_json_ld_1_1() {
+                                    _nest_inner_lists(value)
+                                } else {
+                                    value
+                                };
+
                                 let tex = await!(Context::_expand::<T>(
                                     active_context.clone(),
                                     active_property.to_owned(),
-                                    value
+                                    value,
+                                    options.clone()
                                 ))?;
 
                                 // 7.4.9.3
                                 if let Value::Object(ref obj) = tex {
-                                    if obj.contains_key("@list") {
+                                    if obj.contains_key("@list") && !active_context.is_json_ld_1_1()
+                                    {
                                         return Err(ExpansionError::ListOfLists);
                                     }
                                 }
@@ -361,7 +580,8 @@ impl Context {
                                 expanded_value = await!(Context::_expand::<T>(
                                     active_context.clone(),
                                     active_property.to_owned(),
-                                    value
+                                    value,
+                                    options.clone()
                                 ))?;
                             }
 
@@ -373,6 +593,7 @@ impl Context {
                                         active_context.clone(),
                                         Some(prop),
                                         Value::Object(obj.clone()),
+                                        options.clone()
                                     ))?;
 
                                     if let Value::Object(mut expv) = expanded_value {
@@ -460,6 +681,37 @@ impl Context {
                                 }
                             }
 
+                            "@nest" if active_context.is_json_ld_1_1() => {
+                                let nested_values = match value {
+                                    Value::Array(arr) => arr,
+                                    _ => vec![value],
+                                };
+
+                                for nested_value in nested_values {
+                                    if let Value::Object(nested) = nested_value {
+                                        for (nested_key, nested_value) in nested {
+                                            if active_context.expand_iri(&nested_key, false, true)
+                                                == "@value"
+                                            {
+                                                return Err(ExpansionError::InvalidNestValue);
+                                            }
+
+                                            entries.push_back((nested_key, nested_value));
+                                        }
+                                    } else {
+                                        return Err(ExpansionError::InvalidNestValue);
+                                    }
+                                }
+
+                                continue;
+                            }
+
+                            // these only mean something inside a context, and
+                            // are dropped like unknown keys unless in strict mode
+                            "@base" | "@vocab" | "@container" if options.strict => {
+                                return Err(ExpansionError::InvalidKeyword)
+                            }
+
                             _ => continue,
                         }
 
@@ -469,14 +721,34 @@ impl Context {
                         let mut expanded_value: Option<Value> = None;
                         let item = active_context.terms.get(&key).map(Term::to_owned);
                         if let Some(item) = item {
-                            if let Some(map) = item.container_mapping.clone() {
+                            if item.type_mapping.as_ref().map(String::as_str) == Some("@json") {
+                                // the value is kept as is, as a JSON literal
+                                let mut literal = Map::new();
+                                literal.insert(
+                                    "@value".to_owned(),
+                                    mem::replace(&mut value, Value::Null),
+                                );
+                                literal
+                                    .insert("@type".to_owned(), Value::String("@json".to_owned()));
+                                expanded_value = Some(Value::Object(literal));
+                            } else if let Some(map) = item.container_mapping.clone() {
                                 if map == "@language" && value.is_object() {
                                     // 7.5
                                     let obj = value.as_object().unwrap();
                                     let mut new_arr = Vec::new();
 
                                     for (language, language_value) in obj {
-                                        let language = language.to_lowercase();
+                                        // 1.1 keeps values without a language under @none;
+                                        // an empty key means the same in either mode
+                                        let language = if language.is_empty()
+                                            || (active_context.is_json_ld_1_1()
+                                                && active_context.expand_iri(language, false, true)
+                                                    == "@none")
+                                        {
+                                            None
+                                        } else {
+                                            Some(language.to_lowercase())
+                                        };
 
                                         let language_values = match *language_value {
                                             Value::String(ref string) => {
@@ -499,10 +771,12 @@ impl Context {
 
                                             let mut map = Map::new();
                                             map.insert("@value".to_string(), val);
-                                            map.insert(
-                                                "@language".to_string(),
-                                                Value::String(language.to_string()),
-                                            );
+                                            if let Some(ref language) = language {
+                                                map.insert(
+                                                    "@language".to_string(),
+                                                    Value::String(language.to_string()),
+                                                );
+                                            }
 
                                             new_arr.push(Value::Object(map))
                                         }
@@ -514,6 +788,11 @@ impl Context {
                                     if let Value::Object(obj) = value {
                                         let mut ar = Vec::new();
                                         for (index, mut index_value) in obj {
+                                            let is_none = index.is_empty()
+                                                || (active_context.is_json_ld_1_1()
+                                                    && active_context
+                                                        .expand_iri(&index, false, true)
+                                                        == "@none");
                                             if !index_value.is_array() {
                                                 index_value =
                                                     Value::Array(vec![index_value].into());
@@ -523,13 +802,15 @@ impl Context {
                                                 active_context.clone(),
                                                 Some(key.to_owned()),
                                                 index_value,
+                                                options.clone()
                                             ))?;
                                             if let Value::Array(var) = index_value {
                                                 for mut item in var {
-                                                    if !item
-                                                        .as_object()
-                                                        .unwrap()
-                                                        .contains_key("@index")
+                                                    if !is_none
+                                                        && !item
+                                                            .as_object()
+                                                            .unwrap()
+                                                            .contains_key("@index")
                                                     {
                                                         item.as_object_mut().unwrap().insert(
                                                             "@index".to_owned(),
@@ -544,6 +825,66 @@ impl Context {
                                             }
                                         }
 
+                                        expanded_value = Some(Value::Array(ar));
+                                        value = Value::Null;
+                                    } else {
+                                        unreachable!();
+                                    }
+                                } else if map == "@id" && value.is_object() {
+                                    // id maps, keyed on the @id of each node; @none means no @id
+                                    if let Value::Object(obj) = value {
+                                        let mut ar = Vec::new();
+                                        for (id, mut id_value) in obj {
+                                            if !id_value.is_array() {
+                                                id_value = Value::Array(vec![id_value].into());
+                                            }
+
+                                            // a string is a node reference; its own @id takes
+                                            // precedence over the map key
+                                            if let Value::Array(ref mut var) = id_value {
+                                                for item in var.iter_mut() {
+                                                    if item.is_string() {
+                                                        let mut reference = Map::new();
+                                                        reference.insert(
+                                                            "@id".to_owned(),
+                                                            mem::replace(item, Value::Null),
+                                                        );
+                                                        *item = Value::Object(reference);
+                                                    }
+                                                }
+                                            }
+
+                                            id_value = await!(Context::_expand::<T>(
+                                                active_context.clone(),
+                                                Some(key.to_owned()),
+                                                id_value,
+                                                options.clone()
+                                            ))?;
+                                            if let Value::Array(var) = id_value {
+                                                for mut item in var {
+                                                    if active_context.expand_iri(&id, false, true)
+                                                        != "@none"
+                                                        && !item
+                                                            .as_object()
+                                                            .unwrap()
+                                                            .contains_key("@id")
+                                                    {
+                                                        item.as_object_mut().unwrap().insert(
+                                                            "@id".to_owned(),
+                                                            Value::String(
+                                                                active_context
+                                                                    .expand_iri(&id, true, false),
+                                                            ),
+                                                        );
+                                                    }
+
+                                                    ar.push(item);
+                                                }
+                                            } else {
+                                                unreachable!();
+                                            }
+                                        }
+
                                         expanded_value = Some(Value::Array(ar));
                                         value = Value::Null;
                                     } else {
@@ -555,10 +896,22 @@ impl Context {
 
                         // 7.7
                         if expanded_value == None {
+                            if active_context
+                                .terms
+                                .get(&key)
+                                .and_then(|term| term.container_mapping.as_ref())
+                                .map(String::as_str)
+                                == Some("@list")
+                                && _contains_set_object(&active_context, &value)
+                            {
+                                return Err(ExpansionError::InvalidSetOrListObject);
+                            }
+
                             expanded_value = Some(await!(Context::_expand::<T>(
                                 active_context.to_owned(),
                                 Some(key.to_owned()),
-                                value
+                                value,
+                                options.clone()
                             ))?);
                         }
                         let mut expanded_value = expanded_value.unwrap();
@@ -659,6 +1012,12 @@ impl Context {
                     }
                 }
 
+                if options.ordered {
+                    if let Some(&mut Value::Array(ref mut types)) = result.get_mut("@type") {
+                        types.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+                    }
+                }
+
                 if result.contains_key("@value") {
                     let val = &result["@value"];
 
@@ -679,11 +1038,11 @@ impl Context {
                     }
 
                     match *val {
-                        Value::Null => return Ok(Value::Null),
+                        Value::Null if !is_json_literal => return Ok(Value::Null),
                         Value::String(_) => {}
                         _ => {
                             if result.contains_key("@language") {
-                                return Err(ExpansionError::InvalidTypedValue);
+                                return Err(ExpansionError::InvalidLanguageTaggedString);
                             }
                         }
                     }
@@ -717,6 +1076,10 @@ impl Context {
 
                 if result.len() == 1 && result.contains_key("@language") {
                     Ok(Value::Null)
+                } else if dropped_id && result.is_empty() {
+                    // a node reference whose @id was dropped is left out, rather
+                    // than turning into a blank node
+                    Ok(Value::Null)
                 } else if active_property == None
                     || active_property.as_ref().map(String::as_str) == Some("@graph")
                 {
@@ -727,6 +1090,15 @@ impl Context {
                         Ok(Value::Null)
                     } else if result.len() == 1 && result.contains_key("@id") {
                         Ok(Value::Null)
+                    } else if result.len() == 1
+                        && result
+                            .get("@graph")
+                            .and_then(Value::as_array)
+                            .map(Vec::is_empty)
+                            == Some(true)
+                    {
+                        // a graph wrapper with nothing in it
+                        Ok(Value::Null)
                     } else {
                         Ok(Value::Object(result))
                     }
@@ -743,7 +1115,23 @@ impl Context {
                         Ok(Value::Null)
                     } else {
                         // 2.2
-                        Ok(active_context._expand_value(&activeprop, elem))
+                        let expanded = active_context._expand_value(&activeprop, elem);
+
+                        // values coerced to node references carry an IRI too
+                        if let Some(&Value::String(ref id)) =
+                            expanded.as_object().and_then(|obj| obj.get("@id"))
+                        {
+                            if _check_relative_iri::<T>(
+                                id.to_owned(),
+                                options.relative_iri_handling,
+                            )?
+                            .is_none()
+                            {
+                                return Ok(Value::Null);
+                            }
+                        }
+
+                        Ok(expanded)
                     }
                 } else {
                     // 2.1
@@ -755,7 +1143,16 @@ impl Context {
 
     #[async]
     pub fn expand<T: RemoteContextLoader>(self, elem: Value) -> Result<Value, ExpansionError<T>> {
-        let mut val = await!(Context::_expand::<T>(self, None, elem))?;
+        await!(self.expand_with_options::<T>(elem, ExpansionOptions::default()))
+    }
+
+    #[async]
+    pub(crate) fn expand_with_options<T: RemoteContextLoader>(
+        self,
+        elem: Value,
+        options: ExpansionOptions,
+    ) -> Result<Value, ExpansionError<T>> {
+        let mut val = await!(Context::_expand::<T>(self, None, elem, options))?;
 
         if val
             .as_object()
@@ -17,9 +17,16 @@ mod expand;
 pub mod nodemap;
 pub mod rdf;
 
+#[cfg(test)]
+mod test_util;
+
 mod api;
 pub use api::*;
 
+pub use compact::InverseContext;
+pub use context::Context;
+pub use creation::ContextCache;
+
 use std::error::Error;
 use std::fmt::Debug;
 
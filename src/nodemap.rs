@@ -599,3 +599,160 @@ where
 
     Ok(())
 }
+
+/// Gives every node object in an expanded document an `@id`, using
+/// `generator`. Anonymous nodes get a fresh blank node, and existing blank
+/// node identifiers are relabeled so they can't clash with the new ones.
+pub fn label_blank_nodes<T: BlankNodeGenerator>(element: &mut JValue, generator: &mut T) {
+    match *element {
+        JValue::Array(ref mut arr) => {
+            for item in arr.iter_mut() {
+                label_blank_nodes(item, generator);
+            }
+        }
+
+        JValue::Object(ref mut obj) => {
+            if obj.contains_key("@value") {
+                return;
+            }
+
+            if let Some(list) = obj.get_mut("@list") {
+                label_blank_nodes(list, generator);
+                return;
+            }
+
+            let id = match obj.get("@id") {
+                Some(&JValue::String(ref id)) if id.starts_with("_:") => {
+                    Some(generator.generate_blank_node(Some(id)))
+                }
+                Some(_) => None,
+                None => Some(generator.generate_blank_node(None)),
+            };
+
+            if let Some(id) = id {
+                obj.insert("@id".to_owned(), JValue::String(id));
+            }
+
+            for (key, value) in obj.iter_mut() {
+                match key.as_str() {
+                    "@id" | "@index" => {}
+
+                    "@type" => {
+                        if let JValue::Array(ref mut types) = *value {
+                            for typ in types.iter_mut() {
+                                let label = match *typ {
+                                    JValue::String(ref name) if name.starts_with("_:") => {
+                                        Some(generator.generate_blank_node(Some(name)))
+                                    }
+                                    _ => None,
+                                };
+
+                                if let Some(label) = label {
+                                    *typ = JValue::String(label);
+                                }
+                            }
+                        }
+                    }
+
+                    "@reverse" => {
+                        if let JValue::Object(ref mut reverse) = *value {
+                            for (_, item) in reverse.iter_mut() {
+                                label_blank_nodes(item, generator);
+                            }
+                        }
+                    }
+
+                    _ => label_blank_nodes(value, generator),
+                }
+            }
+        }
+
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::json;
+
+    /// Collects the `@id` of every node object in an expanded document,
+    /// failing on a node object without one.
+    fn node_ids(element: &JValue, ids: &mut Vec<String>) {
+        match *element {
+            JValue::Array(ref arr) => {
+                for item in arr {
+                    node_ids(item, ids);
+                }
+            }
+
+            JValue::Object(ref obj) => {
+                if obj.contains_key("@value") {
+                    return;
+                }
+
+                if let Some(list) = obj.get("@list") {
+                    return node_ids(list, ids);
+                }
+
+                match obj.get("@id") {
+                    Some(&JValue::String(ref id)) => ids.push(id.to_owned()),
+                    _ => panic!("node without an @id: {}", element),
+                }
+
+                for (key, value) in obj {
+                    match key.as_str() {
+                        "@id" | "@type" => {}
+                        "@reverse" => {
+                            for (_, value) in value.as_object().unwrap() {
+                                node_ids(value, ids);
+                            }
+                        }
+                        _ => node_ids(value, ids),
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn label_blank_nodes_gives_every_node_an_id() {
+        let mut expanded = json(
+            r#"[{
+                "http://example.com/knows": [
+                    {"http://example.com/name": [{"@value": "a"}]},
+                    {"@list": [{"http://example.com/name": [{"@value": "b"}]}]}
+                ],
+                "@reverse": {"http://example.com/parent": [{}]}
+            }]"#,
+        );
+
+        label_blank_nodes(&mut expanded, &mut DefaultNodeGenerator::new());
+
+        let mut ids = Vec::new();
+        node_ids(&expanded, &mut ids);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+    }
+
+    #[test]
+    fn label_blank_nodes_relabels_a_blank_node_consistently() {
+        let mut expanded = json(
+            r#"[
+                {"@id": "_:x", "http://example.com/next": [{"@id": "_:x"}]},
+                {"http://example.com/next": [{"@id": "_:x"}]}
+            ]"#,
+        );
+
+        label_blank_nodes(&mut expanded, &mut DefaultNodeGenerator::new());
+
+        let x = expanded[0]["@id"].clone();
+        assert_ne!(x, json(r#""_:x""#));
+        assert_eq!(expanded[0]["http://example.com/next"][0]["@id"], x);
+        assert_eq!(expanded[1]["http://example.com/next"][0]["@id"], x);
+        assert_ne!(expanded[1]["@id"], x);
+    }
+}
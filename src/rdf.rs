@@ -5,7 +5,8 @@
 
 use serde_json::Map;
 use serde_json::Value as JValue;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
 
 use super::nodemap::{generate_node_map, BlankNodeGenerator, NodeMapError, Pointer};
 
@@ -28,6 +29,9 @@ pub struct StringQuad {
     pub contents: QuadContents,
 }
 
+/// A set of quads keyed on graph name. The default graph is keyed as `@default`.
+pub type Dataset = HashMap<String, Vec<StringQuad>>;
+
 /// Predicate for the first item in a list.
 pub const RDF_FIRST: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#first";
 
@@ -37,7 +41,28 @@ pub const RDF_REST: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#r
 /// Object for the end of a list.
 pub const RDF_NIL: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#nil";
 
+/// Datatype of JSON literals, which JSON-LD types as `@json`.
+pub const RDF_JSON: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#JSON";
+
+/// Formats a double in the canonical `xsd:double` form, e.g. `4.2E0`.
+fn canonical_double(value: f64) -> String {
+    let formatted = format!("{:E}", value);
+
+    match formatted.find('E') {
+        Some(exp) if !formatted[..exp].contains('.') => {
+            format!("{}.0{}", &formatted[..exp], &formatted[exp..])
+        }
+        _ => formatted,
+    }
+}
+
 fn object_to_rdf(typeval: Option<String>, value: JValue) -> QuadContents {
+    // JSON literals are serialized without whitespace; object keys come out
+    // sorted, unless the `preserve_order` feature is enabled
+    if typeval.as_ref().map(String::as_str) == Some("@json") {
+        return QuadContents::Object(RDF_JSON.to_owned(), value.to_string(), None);
+    }
+
     match value {
         JValue::String(strval) => {
             let typeval =
@@ -54,14 +79,27 @@ fn object_to_rdf(typeval: Option<String>, value: JValue) -> QuadContents {
         }
 
         JValue::Number(numval) => {
-            let typeval = if numval.is_f64() {
-                "http://www.w3.org/2001/XMLSchema#double"
+            let double = numval.as_f64().unwrap();
+
+            if typeval.as_ref().map(String::as_str)
+                == Some("http://www.w3.org/2001/XMLSchema#double")
+                || (numval.is_f64() && (double.fract() != 0.0 || double.abs() >= 1e21))
+            {
+                let typeval =
+                    typeval.unwrap_or_else(|| "http://www.w3.org/2001/XMLSchema#double".to_owned());
+
+                QuadContents::Object(typeval, canonical_double(double), None)
             } else {
-                "http://www.w3.org/2001/XMLSchema#integer"
-            }
-            .to_owned();
+                let typeval = typeval
+                    .unwrap_or_else(|| "http://www.w3.org/2001/XMLSchema#integer".to_owned());
+                let lexical = if numval.is_f64() {
+                    format!("{:.0}", double)
+                } else {
+                    numval.to_string()
+                };
 
-            QuadContents::Object(typeval, numval.to_string(), None)
+                QuadContents::Object(typeval, lexical, None)
+            }
         }
 
         _ => unreachable!(),
@@ -155,10 +193,7 @@ where
 /// This method needs a blank node generator, and returns a
 /// map keyed on graph. By default, all items go into
 /// `@default`, but if `@graph` is used this may differ.
-pub fn jsonld_to_rdf<T>(
-    element: JValue,
-    generator: &mut T,
-) -> Result<HashMap<String, Vec<StringQuad>>, NodeMapError>
+pub fn jsonld_to_rdf<T>(element: JValue, generator: &mut T) -> Result<Dataset, NodeMapError>
 where
     T: BlankNodeGenerator,
 {
@@ -252,6 +287,11 @@ fn literal_to_json(contents: QuadContents, use_native_types: bool) -> JValue {
                 (None, JValue::String(value))
             } else if typeval == "http://www.w3.org/2001/XMLSchema#string" {
                 (None, JValue::String(value))
+            } else if typeval == RDF_JSON {
+                match from_str(&value) {
+                    Ok(json) => (Some("@json".to_owned()), json),
+                    Err(_) => (Some(typeval), JValue::String(value)),
+                }
             } else {
                 (Some(typeval), JValue::String(value))
             };
@@ -274,15 +314,34 @@ fn literal_to_json(contents: QuadContents, use_native_types: bool) -> JValue {
 ///
 /// This method cannot fail. All RDF is properly translatable into
 /// JSON-LD.
-pub fn rdf_to_jsonld(
-    graphs: HashMap<String, Vec<StringQuad>>,
+pub fn rdf_to_jsonld(graphs: Dataset, use_native_types: bool, use_rdf_type: bool) -> JValue {
+    rdf_to_jsonld_ordered(graphs, use_native_types, use_rdf_type, false)
+}
+
+/// Translates RDF into equivalent JSON-LD, like `rdf_to_jsonld`.
+///
+/// With `ordered` set, graphs and quads are processed in sorted order, so
+/// the values of each property and the `@type` values come out sorted, and
+/// the output doesn't depend on the order of the input quads.
+pub fn rdf_to_jsonld_ordered(
+    graphs: Dataset,
     use_native_types: bool,
     use_rdf_type: bool,
+    ordered: bool,
 ) -> JValue {
     let mut graph_map = Map::new();
     let mut usages: HashMap<String, HashMap<String, Vec<(String, String, String)>>> =
         HashMap::new();
 
+    let mut graphs: Vec<_> = graphs.into_iter().collect();
+    if ordered {
+        graphs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for &mut (_, ref mut triples) in graphs.iter_mut() {
+            triples.sort_by(|a, b| quad_key(a).cmp(&quad_key(b)));
+        }
+    }
+
     for (graph, triples) in graphs {
         if !graph_map.contains_key(&graph) {}
 
@@ -373,6 +432,11 @@ pub fn rdf_to_jsonld(
         graph_map.insert(graph, JValue::Object(node_map));
     }
 
+    let mut usages: Vec<_> = usages.into_iter().collect();
+    if ordered {
+        usages.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
     for (name, mut graph_object) in usages {
         if !graph_object.contains_key(RDF_NIL) {
             continue;
@@ -466,37 +530,571 @@ pub fn rdf_to_jsonld(
         }
     }
 
+    let mut default_graph = match graph_map.remove("@default") {
+        Some(JValue::Object(val)) => val,
+        _ => Map::new(),
+    };
+
+    // named graphs hang off a node in the default graph, even one that has
+    // no triples of its own
+    for name in graph_map.keys() {
+        if !default_graph.contains_key(name) {
+            let mut input = Map::new();
+            input.insert("@id".to_owned(), JValue::String(name.to_owned()));
+
+            default_graph.insert(name.to_owned(), JValue::Object(input));
+        }
+    }
+
     let mut result = Map::new();
-    if let Some(JValue::Object(val)) = graph_map.remove("@default") {
-        for (subject, mut node) in val {
-            if graph_map.contains_key(&subject) {
-                let mut arr = Vec::new();
-                if let Some(JValue::Object(val)) = graph_map.remove(&subject) {
-                    for (_, n) in val {
-                        if {
-                            let obj_n = n.as_object().unwrap();
-                            obj_n.len() > 1 || !obj_n.contains_key("@id")
-                        } {
-                            arr.push(n);
-                        }
+    for (subject, mut node) in default_graph {
+        if graph_map.contains_key(&subject) {
+            let mut arr = Vec::new();
+            if let Some(JValue::Object(val)) = graph_map.remove(&subject) {
+                for (_, n) in val {
+                    if {
+                        let obj_n = n.as_object().unwrap();
+                        obj_n.len() > 1 || !obj_n.contains_key("@id")
+                    } {
+                        arr.push(n);
                     }
                 }
+            }
+
+            node.as_object_mut()
+                .unwrap()
+                .insert("@graph".to_owned(), JValue::Array(arr));
+        }
+
+        if {
+            let nobj = node.as_object().unwrap();
+            nobj.len() > 1 || !nobj.contains_key("@id")
+        } {
+            result.insert(subject, node);
+        }
+    }
+
+    JValue::Object(result)
+}
+
+fn blank_nodes(dataset: &Dataset) -> HashSet<String> {
+    let mut labels = HashSet::new();
 
-                node.as_object_mut()
-                    .unwrap()
-                    .insert("@graph".to_owned(), JValue::Array(arr));
+    for (graph, quads) in dataset {
+        labels.insert(graph.to_owned());
+
+        for quad in quads {
+            labels.insert(quad.subject_id.to_owned());
+            labels.insert(quad.predicate_id.to_owned());
+
+            if let QuadContents::Id(ref id) = quad.contents {
+                labels.insert(id.to_owned());
             }
+        }
+    }
+
+    labels.retain(|label| label.starts_with("_:"));
+    labels
+}
+
+fn relabel(id: String, labels: &HashMap<String, String>) -> String {
+    match labels.get(&id) {
+        Some(label) => label.to_owned(),
+        None => id,
+    }
+}
 
-            if {
-                let nobj = node.as_object().unwrap();
-                nobj.len() > 1 || !nobj.contains_key("@id")
-            } {
-                result.insert(subject, node);
+/// Adds all the quads of `other` to `dataset`.
+///
+/// With `relabel` set, the blank nodes of `other` are renamed so none of them
+/// collides with a blank node already in `dataset`, and a `_:b0` in each stays
+/// two separate nodes. Otherwise blank nodes with the same label are taken to
+/// be the same node.
+pub fn merge_datasets(dataset: &mut Dataset, other: Dataset, relabel_blank_nodes: bool) {
+    let mut labels = HashMap::new();
+
+    if relabel_blank_nodes {
+        let used = blank_nodes(dataset);
+        let mut incoming: Vec<_> = blank_nodes(&other).into_iter().collect();
+        incoming.sort();
+
+        let mut i = 0;
+        for label in incoming {
+            let mut new_label = format!("_:b{}", i);
+            while used.contains(&new_label) {
+                i += 1;
+                new_label = format!("_:b{}", i);
             }
+            i += 1;
+
+            labels.insert(label, new_label);
         }
+    }
+
+    for (graph, quads) in other {
+        let target = dataset
+            .entry(relabel(graph, &labels))
+            .or_insert_with(Vec::new);
+
+        for quad in quads {
+            target.push(StringQuad {
+                subject_id: relabel(quad.subject_id, &labels),
+                predicate_id: relabel(quad.predicate_id, &labels),
+                contents: match quad.contents {
+                    QuadContents::Id(id) => QuadContents::Id(relabel(id, &labels)),
+                    contents => contents,
+                },
+            });
+        }
+    }
+}
+
+fn quad_key(quad: &StringQuad) -> (&str, &str, &str, &str, &str) {
+    match quad.contents {
+        QuadContents::Id(ref id) => (&quad.subject_id, &quad.predicate_id, id, "", ""),
+        QuadContents::Object(ref typeval, ref value, ref lang) => (
+            &quad.subject_id,
+            &quad.predicate_id,
+            value,
+            typeval,
+            lang.as_ref().map(|lang| lang.as_str()).unwrap_or(""),
+        ),
+    }
+}
+
+fn write_id<W: Write>(id: &str, out: &mut W) -> io::Result<()> {
+    if id.starts_with("_:") {
+        out.write_all(id.as_bytes())
     } else {
-        unreachable!();
+        write!(out, "<{}>", id)
+    }
+}
+
+fn write_escaped<W: Write>(value: &str, out: &mut W) -> io::Result<()> {
+    let mut buf = [0; 4];
+
+    for c in value.chars() {
+        match c {
+            '\\' => out.write_all(b"\\\\")?,
+            '"' => out.write_all(b"\\\"")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            _ => out.write_all(c.encode_utf8(&mut buf).as_bytes())?,
+        }
     }
 
-    JValue::Object(result)
+    Ok(())
+}
+
+/// Serializes a dataset as N-Quads, writing one quad at a time to `out`.
+///
+/// Graphs and the quads inside them are written in sorted order, with
+/// duplicate quads dropped, so the output is stable between runs. Only
+/// the quads themselves are buffered, never the serialized text.
+pub fn write_nquads<W: Write>(dataset: &Dataset, out: &mut W) -> io::Result<()> {
+    let mut graphs: Vec<_> = dataset.iter().collect();
+    graphs.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (graph, quads) in graphs {
+        let mut quads: Vec<_> = quads.iter().collect();
+        quads.sort_by(|a, b| quad_key(a).cmp(&quad_key(b)));
+        quads.dedup_by(|a, b| quad_key(a) == quad_key(b));
+
+        for quad in quads {
+            write_id(&quad.subject_id, out)?;
+            out.write_all(b" ")?;
+            write_id(&quad.predicate_id, out)?;
+            out.write_all(b" ")?;
+
+            match quad.contents {
+                QuadContents::Id(ref id) => write_id(id, out)?,
+                QuadContents::Object(ref typeval, ref value, ref lang) => {
+                    out.write_all(b"\"")?;
+                    write_escaped(value, out)?;
+                    out.write_all(b"\"")?;
+
+                    if let Some(ref lang) = *lang {
+                        write!(out, "@{}", lang)?;
+                    } else if typeval != "http://www.w3.org/2001/XMLSchema#string" {
+                        out.write_all(b"^^")?;
+                        write_id(typeval, out)?;
+                    }
+                }
+            }
+
+            if graph != "@default" {
+                out.write_all(b" ")?;
+                write_id(graph, out)?;
+            }
+
+            out.write_all(b" .\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serializes a dataset as an N-Quads string.
+///
+/// This is a convenience wrapper around `write_nquads`; prefer that one
+/// for large datasets.
+pub fn to_nquads(dataset: &Dataset) -> String {
+    let mut out = Vec::new();
+    write_nquads(dataset, &mut out).unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nodemap::DefaultNodeGenerator;
+    use test_util::json;
+
+    const XSD_STRING: &'static str = "http://www.w3.org/2001/XMLSchema#string";
+
+    fn literal(subject: &str, predicate: &str, value: &str) -> StringQuad {
+        StringQuad {
+            subject_id: subject.to_owned(),
+            predicate_id: predicate.to_owned(),
+            contents: QuadContents::Object(XSD_STRING.to_owned(), value.to_owned(), None),
+        }
+    }
+
+    fn dataset(graph: &str, quads: Vec<StringQuad>) -> Dataset {
+        let mut dataset = Dataset::new();
+        dataset.insert(graph.to_owned(), quads);
+
+        dataset
+    }
+
+    #[test]
+    fn rdf_to_jsonld_ordered_sorts_property_values() {
+        let quads = || {
+            dataset(
+                "@default",
+                vec![
+                    literal("http://example.com/s", "http://example.com/p", "b"),
+                    literal("http://example.com/s", "http://example.com/p", "a"),
+                ],
+            )
+        };
+        let values = |json: &JValue| {
+            json["http://example.com/s"]["http://example.com/p"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|value| value["@value"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            values(&rdf_to_jsonld(quads(), false, false)),
+            vec!["b", "a"]
+        );
+        assert_eq!(
+            values(&rdf_to_jsonld_ordered(quads(), false, false, true)),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn canonical_double_always_has_a_fraction_and_exponent() {
+        assert_eq!(canonical_double(4.2), "4.2E0");
+        assert_eq!(canonical_double(1.0), "1.0E0");
+        assert_eq!(canonical_double(0.0), "0.0E0");
+        assert_eq!(canonical_double(-1.5), "-1.5E0");
+        assert_eq!(canonical_double(1e21), "1.0E21");
+        assert_eq!(canonical_double(0.00125), "1.25E-3");
+    }
+
+    #[test]
+    fn write_escaped_escapes_quotes_backslashes_and_line_breaks() {
+        let mut out = Vec::new();
+        write_escaped("a\"b\\c\nd\te\rf", &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), r#"a\"b\\c\nd\te\rf"#);
+    }
+
+    #[test]
+    fn to_nquads_sorts_and_deduplicates_quads() {
+        let mut dataset = dataset(
+            "@default",
+            vec![
+                literal("http://example.com/s", "http://example.com/p", "b"),
+                literal("http://example.com/s", "http://example.com/p", "a"),
+                literal("http://example.com/s", "http://example.com/p", "a"),
+                StringQuad {
+                    subject_id: "http://example.com/s".to_owned(),
+                    predicate_id: "http://example.com/q".to_owned(),
+                    contents: QuadContents::Id("_:b0".to_owned()),
+                },
+            ],
+        );
+        dataset.insert(
+            "http://example.com/g".to_owned(),
+            vec![
+                StringQuad {
+                    subject_id: "_:b0".to_owned(),
+                    predicate_id: "http://example.com/p".to_owned(),
+                    contents: QuadContents::Object(
+                        "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString".to_owned(),
+                        "hi".to_owned(),
+                        Some("en".to_owned()),
+                    ),
+                },
+                StringQuad {
+                    subject_id: "_:b0".to_owned(),
+                    predicate_id: "http://example.com/p".to_owned(),
+                    contents: QuadContents::Object(
+                        "http://www.w3.org/2001/XMLSchema#integer".to_owned(),
+                        "1".to_owned(),
+                        None,
+                    ),
+                },
+            ],
+        );
+
+        assert_eq!(
+            to_nquads(&dataset),
+            "<http://example.com/s> <http://example.com/p> \"a\" .\n\
+             <http://example.com/s> <http://example.com/p> \"b\" .\n\
+             <http://example.com/s> <http://example.com/q> _:b0 .\n\
+             _:b0 <http://example.com/p> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> \
+             <http://example.com/g> .\n\
+             _:b0 <http://example.com/p> \"hi\"@en <http://example.com/g> .\n"
+        );
+    }
+
+    #[test]
+    fn merge_datasets_keeps_blank_nodes_apart_when_relabeling() {
+        let ours = || {
+            dataset(
+                "@default",
+                vec![literal("_:b0", "http://example.com/p", "a")],
+            )
+        };
+        let theirs = || {
+            dataset(
+                "@default",
+                vec![
+                    literal("_:b0", "http://example.com/p", "b"),
+                    StringQuad {
+                        subject_id: "_:b1".to_owned(),
+                        predicate_id: "http://example.com/p".to_owned(),
+                        contents: QuadContents::Id("_:b0".to_owned()),
+                    },
+                ],
+            )
+        };
+
+        let mut merged = ours();
+        merge_datasets(&mut merged, theirs(), true);
+        assert_eq!(
+            to_nquads(&merged),
+            "_:b0 <http://example.com/p> \"a\" .\n\
+             _:b1 <http://example.com/p> \"b\" .\n\
+             _:b2 <http://example.com/p> _:b1 .\n"
+        );
+
+        let mut merged = ours();
+        merge_datasets(&mut merged, theirs(), false);
+        assert_eq!(
+            to_nquads(&merged),
+            "_:b0 <http://example.com/p> \"a\" .\n\
+             _:b0 <http://example.com/p> \"b\" .\n\
+             _:b1 <http://example.com/p> _:b0 .\n"
+        );
+    }
+
+    #[test]
+    fn rdf_to_jsonld_puts_named_graphs_next_to_default_graph_nodes() {
+        let mut quads = dataset(
+            "@default",
+            vec![literal("http://example.com/s", "http://example.com/p", "a")],
+        );
+        quads.insert(
+            "http://example.com/g".to_owned(),
+            vec![literal("http://example.com/t", "http://example.com/p", "b")],
+        );
+
+        let json = rdf_to_jsonld(quads, false, false);
+        let nodes = json.as_object().unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes["http://example.com/s"].get("@graph").is_none());
+        assert_eq!(
+            nodes["http://example.com/s"]["http://example.com/p"][0]["@value"],
+            "a"
+        );
+
+        let graph = nodes["http://example.com/g"]["@graph"].as_array().unwrap();
+        assert_eq!(graph.len(), 1);
+        assert_eq!(graph[0]["@id"], "http://example.com/t");
+        assert!(!json.to_string().contains("@default"));
+    }
+
+    #[test]
+    fn rdf_to_jsonld_handles_datasets_without_a_default_graph() {
+        let quads = dataset(
+            "http://example.com/g",
+            vec![literal("http://example.com/t", "http://example.com/p", "b")],
+        );
+
+        let json = rdf_to_jsonld(quads, false, false);
+
+        assert_eq!(json.as_object().unwrap().len(), 1);
+        assert_eq!(
+            json["http://example.com/g"]["@graph"][0]["@id"],
+            "http://example.com/t"
+        );
+    }
+
+    const RDF_TYPE: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+    #[test]
+    fn rdf_to_jsonld_only_folds_iri_valued_rdf_types_into_type() {
+        let quads = dataset(
+            "@default",
+            vec![
+                StringQuad {
+                    subject_id: "http://example.com/s".to_owned(),
+                    predicate_id: RDF_TYPE.to_owned(),
+                    contents: QuadContents::Id("http://example.com/T".to_owned()),
+                },
+                literal("http://example.com/t", RDF_TYPE, "not an IRI"),
+            ],
+        );
+
+        let json = rdf_to_jsonld(quads, false, false);
+
+        let iri_typed = &json["http://example.com/s"];
+        assert_eq!(iri_typed["@type"].as_array().unwrap().len(), 1);
+        assert_eq!(iri_typed["@type"][0], "http://example.com/T");
+        assert!(iri_typed.get(RDF_TYPE).is_none());
+
+        let literal_typed = &json["http://example.com/t"];
+        assert!(literal_typed.get("@type").is_none());
+        assert_eq!(literal_typed[RDF_TYPE][0]["@value"], "not an IRI");
+    }
+
+    fn nquads(expanded: &str) -> String {
+        let dataset = jsonld_to_rdf(json(expanded), &mut DefaultNodeGenerator::new()).unwrap();
+
+        to_nquads(&dataset)
+    }
+
+    #[test]
+    fn jsonld_to_rdf_drops_every_index() {
+        let indexed = nquads(
+            r#"[{
+                "@id": "http://example.com/g",
+                "@index": "graph",
+                "@graph": [{
+                    "@id": "http://example.com/s",
+                    "@index": "node",
+                    "http://example.com/p": [
+                        {"@value": "v", "@index": "value"},
+                        {"@id": "http://example.com/o", "@index": "reference"},
+                        {"@list": [{"@value": "x", "@index": "item"}], "@index": "list"}
+                    ]
+                }]
+            }]"#,
+        );
+        let plain = nquads(
+            r#"[{
+                "@id": "http://example.com/g",
+                "@graph": [{
+                    "@id": "http://example.com/s",
+                    "http://example.com/p": [
+                        {"@value": "v"},
+                        {"@id": "http://example.com/o"},
+                        {"@list": [{"@value": "x"}]}
+                    ]
+                }]
+            }]"#,
+        );
+
+        assert_eq!(indexed, plain);
+        assert!(!indexed.contains("@index"));
+        assert_eq!(indexed.lines().count(), 5);
+    }
+
+    #[test]
+    fn rdf_to_jsonld_keeps_rdf_type_as_a_property_under_use_rdf_type() {
+        let quads = || {
+            dataset(
+                "@default",
+                vec![StringQuad {
+                    subject_id: "http://example.com/s".to_owned(),
+                    predicate_id: RDF_TYPE.to_owned(),
+                    contents: QuadContents::Id("http://example.com/T".to_owned()),
+                }],
+            )
+        };
+
+        let folded = rdf_to_jsonld(quads(), false, false);
+        assert_eq!(
+            folded["http://example.com/s"]["@type"][0],
+            "http://example.com/T"
+        );
+        assert!(folded["http://example.com/s"].get(RDF_TYPE).is_none());
+
+        let kept = rdf_to_jsonld(quads(), false, true);
+        assert_eq!(
+            kept["http://example.com/s"][RDF_TYPE][0]["@id"],
+            "http://example.com/T"
+        );
+        assert!(kept["http://example.com/s"].get("@type").is_none());
+    }
+
+    #[test]
+    fn plain_and_xsd_string_literals_are_the_same_literal() {
+        let quads = nquads(&format!(
+            r#"[{{
+                "@id": "http://example.com/s",
+                "http://example.com/p": [{{"@value": "v"}}, {{"@value": "v", "@type": "{}"}}]
+            }}]"#,
+            XSD_STRING
+        ));
+        assert_eq!(
+            quads,
+            "<http://example.com/s> <http://example.com/p> \"v\" .\n"
+        );
+
+        for &use_native_types in [false, true].iter() {
+            let json = rdf_to_jsonld(
+                dataset(
+                    "@default",
+                    vec![literal("http://example.com/s", "http://example.com/p", "v")],
+                ),
+                use_native_types,
+                false,
+            );
+
+            let value = &json["http://example.com/s"]["http://example.com/p"][0];
+            assert_eq!(value["@value"], "v");
+            assert!(value.get("@type").is_none());
+        }
+    }
+
+    #[test]
+    fn jsonld_to_rdf_labels_a_blank_node_the_same_in_every_graph() {
+        let quads = nquads(
+            r#"[
+                {"@id": "_:x", "http://example.com/p": [{"@value": "default"}]},
+                {
+                    "@id": "http://example.com/g",
+                    "@graph": [{"@id": "_:x", "http://example.com/p": [{"@value": "named"}]}]
+                }
+            ]"#,
+        );
+
+        assert_eq!(
+            quads,
+            "_:b0 <http://example.com/p> \"default\" .\n\
+             _:b0 <http://example.com/p> \"named\" <http://example.com/g> .\n"
+        );
+    }
 }
@@ -0,0 +1,63 @@
+//! Helpers shared by the unit tests of every module.
+
+use serde_json::Value;
+use std::io;
+
+use super::RemoteContextLoader;
+
+use futures::future;
+
+/// Parses `text` as JSON.
+pub fn json(text: &str) -> Value {
+    ::serde_json::from_str(text).unwrap()
+}
+
+/// Serves a fixed set of remote contexts under `http://example.com/`, and
+/// fails with `NotFound` on any other URL.
+#[derive(Debug)]
+pub struct StubLoader;
+
+impl RemoteContextLoader for StubLoader {
+    type Error = io::Error;
+    type Future = future::FutureResult<Value, io::Error>;
+
+    fn load_context(url: String) -> Self::Future {
+        let context = match url.as_str() {
+            // references the next one by a path relative to its own URL
+            "http://example.com/contexts/a.jsonld" => {
+                r#"{"@context": ["b.jsonld", {"a": "http://example.com/a"}]}"#.to_owned()
+            }
+            "http://example.com/contexts/b.jsonld" => {
+                r#"{"@context": {"b": "http://example.com/b"}}"#.to_owned()
+            }
+
+            // two levels of @import
+            "http://example.com/import/outer.jsonld" => r#"{"@context": {
+                    "@version": 1.1,
+                    "@import": "inner.jsonld",
+                    "o": "http://example.com/o"
+                }}"#
+            .to_owned(),
+            "http://example.com/import/inner.jsonld" => {
+                r#"{"@context": {"i": "http://example.com/i"}}"#.to_owned()
+            }
+            "http://example.com/import/self.jsonld" => {
+                r#"{"@context": {"@import": "self.jsonld"}}"#.to_owned()
+            }
+
+            // an endless chain of imports: n.jsonld imports n+1.jsonld
+            _ if url.starts_with("http://example.com/chain/") => {
+                let n: usize = url["http://example.com/chain/".len()..]
+                    .trim_right_matches(".jsonld")
+                    .parse()
+                    .unwrap();
+
+                format!(r#"{{"@context": {{"@import": "{}.jsonld"}}}}"#, n + 1)
+            }
+
+            _ => return future::err(io::Error::new(io::ErrorKind::NotFound, url)),
+        };
+
+        future::ok(json(&context))
+    }
+}